@@ -15,9 +15,11 @@ class feedMeDaddy:
 	quality_divider = 115
 	progress_modifier = 80
 	quality_modifier = 70
+	expert = False
 	craftsmanship = 4041
 	control = 3959
 	max_cp = 602
+	specialist = False
 
 	# Config
 	depth = 10
@@ -3,16 +3,75 @@
 use std::fmt::Debug;
 use lazy_static::lazy_static;
 use crate::craft::Craft;
-use crate::specs::{Buff, Success};
+use crate::config::{self, ConfigError, ConfigTable};
+use std::str::FromStr;
+use strum::IntoEnumIterator;
+use strum_macros::{EnumIter, EnumString};
+use crate::specs::{Buff, Condition, GameVersion, Success};
 
+/// Identifies an action independently of its values, used for the special rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, EnumIter, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum ActionId {
+    MuscleMemory,
+    Reflect,
+    BasicSynthesis,
+    CarefulSynthesis,
+    Groundwork,
+    PrudentSynthesis,
+    DelicateSynthesis,
+    RapidSynthesis,
+    IntensiveSynthesis,
+    FocusedSynthesis,
+    BasicTouch,
+    StandardTouch,
+    ByregotBlessing,
+    PrudentTouch,
+    PreparatoryTouch,
+    AdvancedTouch,
+    TrainedFinesse,
+    TrainedEye,
+    HastyTouch,
+    PreciseTouch,
+    FocusedTouch,
+    MastersMend,
+    WasteNot,
+    WasteNotII,
+    Manipulation,
+    Veneration,
+    GreatStrides,
+    Innovation,
+    Observe,
+    TricksOfTheTrade,
+    FinalAppraisal,
+    CarefulObservation,
+    HeartAndSoul,
+    ImmaculateMend,
+    TrainedPerfection,
+    RefinedTouch,
+    DaringTouch,
+    QuickInnovation,
+    /// An action defined in an actions file, by its index among them
+    #[strum(disabled)]
+    Custom(u8),
+}
+
+#[derive(Clone)]
 pub struct Action {
+    pub id: ActionId,
     pub name: String,
     pub dur: u32,
     pub cp: u32,
     pub progress: u32,
     pub quality: u32,
     pub buff: Option<(Buff, u8)>,
+    pub success: u32,
+    pub level: u32,
+    // Level of the trait raising the progress, and the progress below it
+    pub trait_upgrade: Option<(u32, u32)>,
     pub short_name: String,
+    /// The action whose special rules (combos, conditions, limited uses...) it follows, none for plain actions
+    pub rules: Option<ActionId>,
 }
 
 pub struct ActionBuilder {
@@ -20,7 +79,7 @@ pub struct ActionBuilder {
 }
 
 impl ActionBuilder {
-    pub fn new(name: &str) -> Self {
+    pub fn new(id: ActionId, name: &str) -> Self {
         let mut short_name = name.to_string();
         short_name = short_name.replace("II", "2");
         short_name.retain(|c| !c.is_whitespace() && c != '\'');
@@ -28,16 +87,23 @@ impl ActionBuilder {
         if name == "Basic Synthesis" { short_name = "basicSynth".to_string(); }
         if name == "Groundwork" { short_name = "groundwork".to_string(); }
         if name == "Careful Synthesis" { short_name = "carefulSynthesis".to_string(); }
+        if name == "Tricks of the Trade" { short_name = "tricksOfTheTrade".to_string(); }
+        if name == "Heart and Soul" { short_name = "heartAndSoul".to_string(); }
 
         Self {
             action: Action {
+                id,
                 name: name.to_string(),
                 dur: 10,
                 cp: 0,
                 progress: 0,
                 quality: 0,
                 buff: None,
+                success: 100,
+                level: 1,
+                trait_upgrade: None,
                 short_name,
+                rules: Some(id),
             }
         }
     }
@@ -61,6 +127,22 @@ impl ActionBuilder {
         self.action.buff = buff;
         self
     }
+    pub fn success(mut self, success: u32) -> Self {
+        self.action.success = success;
+        self
+    }
+    pub fn level(mut self, level: u32) -> Self {
+        self.action.level = level;
+        self
+    }
+    pub fn rules(mut self, rules: Option<ActionId>) -> Self {
+        self.action.rules = rules;
+        self
+    }
+    pub fn trait_upgrade(mut self, level: u32, progress: u32) -> Self {
+        self.action.trait_upgrade = Some((level, progress));
+        self
+    }
     pub fn build(self) -> Action {
         self.action
     }
@@ -68,22 +150,30 @@ impl ActionBuilder {
 
 impl PartialEq for Action {
     fn eq(&self, other: &Self) -> bool {
-        self.cp == other.cp && self.dur == other.dur && self.progress == other.progress && self.quality == other.quality
+        self.id == other.id
     }
 }
 
 impl Action {
+    /// Whether the action follows the special rules of an action
+    pub fn follows(&self, id: ActionId) -> bool {
+        self.rules == Some(id)
+    }
     pub fn get_name(&self) -> String {
         self.name.clone()
     }
     pub fn get_cp_cost(&self, _craft: &Craft) -> u32 {
-        if self == &ACTIONS.standard_touch && _craft.buffs.basic_touch > 0 { return 18; };
-        if self == &ACTIONS.advanced_touch && _craft.buffs.standard_touch > 0 { return 18; };
-        self.cp
+        let mut cp = self.cp;
+        if self.follows(ActionId::StandardTouch) && _craft.buffs.basic_touch > 0 { cp = 18; };
+        if self.follows(ActionId::AdvancedTouch) && _craft.buffs.standard_touch > 0 { cp = 18; };
+        if self.follows(ActionId::AdvancedTouch) && _craft.buffs.observe > 0 && _craft.args.game_version == GameVersion::Dawntrail { cp = 18; };
+        if _craft.condition == Condition::Pliant {
+            cp = cp.div_ceil(2);
+        }
+        cp
     }
     pub fn get_durability_cost(&self, _craft: &Craft) -> u32 {
-        if self == &ACTIONS.masters_mend { return 30; }
-        if self.dur == 0 || self.progress == 0 && self.quality == 0 {
+        if !self.uses_durability() || _craft.buffs.trained_perfection > 0 {
             return 0;
         }
         let mut dur = self.dur;
@@ -93,10 +183,30 @@ impl Action {
         if dur < 5 {
             dur = 5;
         }
+        if _craft.condition == Condition::Sturdy {
+            dur = dur.div_ceil(2);
+        }
         dur
     }
+    /// Whether the action wears the item down, before any reduction
+    pub fn uses_durability(&self) -> bool {
+        !(self.dur == 0 || self.progress == 0 && self.quality == 0)
+    }
+    /// The durability given back by the action
+    pub fn get_durability_restore(&self, _craft: &Craft) -> u32 {
+        if self.follows(ActionId::MastersMend) { return 30; }
+        if self.follows(ActionId::ImmaculateMend) { return _craft.recipe.durability; }
+        0
+    }
+    /// The progress potency of the action at the level of the crafter
+    pub fn get_base_progress(&self, _craft: &Craft) -> u32 {
+        match self.trait_upgrade {
+            Some((level, progress)) if _craft.stats.level < level => progress,
+            _ => self.progress,
+        }
+    }
     pub fn get_progress(&self, _craft: &Craft) -> u32 {
-        let mut prog = self.progress as f64;
+        let mut prog = self.get_base_progress(_craft) as f64;
         if self.dur == 20 && _craft.durability < self.get_durability_cost(_craft) as i32 {
             prog /= 2.0;
         }
@@ -107,11 +217,12 @@ impl Action {
         if _craft.buffs.veneration > 0 {
             mult += 0.5;
         }
-        (prog * mult).floor() as u32
+        let condition_mult = _craft.condition.progress_multiplier();
+        (prog * mult * condition_mult).floor() as u32
     }
     pub fn get_quality(&self, _craft: &Craft) -> u32 {
         let mut qual = self.quality as f64;
-        if self == &ACTIONS.byregot_blessing {
+        if self.follows(ActionId::ByregotBlessing) {
             qual = (100 + 20 * _craft.buffs.inner_quiet as u32) as f64;
         }
         if self.dur == 20 && _craft.durability < self.get_durability_cost(_craft) as i32 {
@@ -125,27 +236,72 @@ impl Action {
             mult += 0.5;
         }
         let iq_mult = (_craft.buffs.inner_quiet as f64 * 0.1) + 1.0;
-        (qual * mult * iq_mult).floor() as u32
+        let condition_mult = _craft.condition.quality_multiplier();
+        (qual * mult * iq_mult * condition_mult).floor() as u32
     }
     pub fn get_buff(&self) -> Option<(Buff, u8)> {
         self.buff
     }
+    /// The chance of success of the action in percent, once the condition is applied
+    pub fn get_success_rate(&self, _craft: &Craft) -> u32 {
+        if (self.follows(ActionId::FocusedSynthesis) || self.follows(ActionId::FocusedTouch)) && _craft.buffs.observe > 0 {
+            return 100;
+        }
+        (self.success + _craft.condition.success_bonus()).min(100)
+    }
+    /// Whether the action needs a Good or Excellent condition (or Heart and Soul)
+    pub fn needs_good_condition(&self) -> bool {
+        self.follows(ActionId::IntensiveSynthesis) || self.follows(ActionId::PreciseTouch) || self.follows(ActionId::TricksOfTheTrade)
+    }
+    /// Whether the action counts as a step for the buffs durations
+    pub fn ticks_buffs(&self) -> bool {
+        !(self.follows(ActionId::FinalAppraisal) || self.follows(ActionId::CarefulObservation) || self.follows(ActionId::HeartAndSoul)
+            || self.follows(ActionId::QuickInnovation))
+    }
     pub fn can_use(&self, _craft: &Craft) -> bool {
+        if !_craft.args.game_version.has_action(self.id) || _craft.stats.level < self.level {
+            return false;
+        }
         if self.get_cp_cost(_craft) > _craft.cp as u32 {
             return false;
         }
         if _craft.success != Success::Pending {
             return false;
         }
-        if self == &ACTIONS.byregot_blessing {
+        if self.follows(ActionId::ByregotBlessing) {
             return _craft.buffs.inner_quiet > 0;
         }
-        if self == &ACTIONS.muscle_memory || self == &ACTIONS.reflect {
+        if self.follows(ActionId::MuscleMemory) || self.follows(ActionId::Reflect) {
             return _craft.step_count == 0;
         }
-        if self == &ACTIONS.trained_finesse {
+        if self.follows(ActionId::TrainedEye) {
+            return _craft.step_count == 0 && !_craft.recipe.expert
+                && _craft.stats.level >= _craft.recipe.class_job_level + 10;
+        }
+        if self.follows(ActionId::TrainedFinesse) {
             return _craft.buffs.inner_quiet >= 10;
         }
+        if self.follows(ActionId::PrudentTouch) || self.follows(ActionId::PrudentSynthesis) {
+            return _craft.buffs.waste_not == 0;
+        }
+        if self.needs_good_condition() {
+            return matches!(_craft.condition, Condition::Good | Condition::Excellent) || _craft.buffs.heart_and_soul > 0;
+        }
+        if self.follows(ActionId::CarefulObservation) {
+            return _craft.stats.specialist && _craft.buffs.careful_observation < 3;
+        }
+        if self.follows(ActionId::HeartAndSoul) {
+            return _craft.stats.specialist && _craft.buffs.heart_and_soul_used == 0;
+        }
+        if self.follows(ActionId::TrainedPerfection) {
+            return _craft.buffs.trained_perfection_used == 0;
+        }
+        if self.follows(ActionId::DaringTouch) {
+            return _craft.buffs.expedience > 0;
+        }
+        if self.follows(ActionId::QuickInnovation) {
+            return _craft.stats.specialist && _craft.buffs.quick_innovation_used == 0 && _craft.buffs.innovation == 0;
+        }
         true
     }
 }
@@ -156,7 +312,7 @@ impl Debug for Action {
     }
 }
 
-
+#[derive(Debug, Clone)]
 pub struct ActionList {
     pub muscle_memory: Action,
     pub reflect: Action,
@@ -172,6 +328,7 @@ pub struct ActionList {
     pub preparatory_touch: Action,
     pub advanced_touch: Action,
     pub trained_finesse: Action,
+    pub trained_eye: Action,
     pub masters_mend: Action,
     pub waste_not: Action,
     pub waste_not_ii: Action,
@@ -179,45 +336,280 @@ pub struct ActionList {
     pub veneration: Action,
     pub great_strides: Action,
     pub innovation: Action,
-    //pub observe: Action,
-    //pub focused_synthesis: Action,
+    pub observe: Action,
+    pub focused_synthesis: Action,
+    pub focused_touch: Action,
+    pub hasty_touch: Action,
+    pub rapid_synthesis: Action,
+    pub intensive_synthesis: Action,
+    pub precise_touch: Action,
+    pub tricks_of_the_trade: Action,
+    pub final_appraisal: Action,
+    pub careful_observation: Action,
+    pub heart_and_soul: Action,
+    pub immaculate_mend: Action,
+    pub trained_perfection: Action,
+    pub refined_touch: Action,
+    pub daring_touch: Action,
+    pub quick_innovation: Action,
+    /// The actions defined in an actions file, see `ActionId::Custom`
+    pub custom: Vec<Action>,
 }
 
 impl Default for ActionList {
     fn default() -> Self {
         Self {
-            muscle_memory: ActionBuilder::new("Muscle Memory").cp(6).progress(300).buff(Some((Buff::MuscleMemory, 5))).build(),
-            reflect: ActionBuilder::new("Reflect").cp(6).quality(100).buff(Some((Buff::InnerQuiet, 1))).build(),
-
-            basic_synthesis: ActionBuilder::new("Basic Synthesis").progress(120).build(),
-            careful_synthesis: ActionBuilder::new("Careful Synthesis").cp(7).progress(180).build(),
-            groundwork: ActionBuilder::new("Groundwork").cp(18).dur(20).progress(360).build(),
-            prudent_synthesis: ActionBuilder::new("Prudent Synthesis").cp(18).dur(5).progress(180).build(),
-            delicate_synthesis: ActionBuilder::new("Delicate Synthesis").cp(32).progress(100).quality(100).build(),
-
-            basic_touch: ActionBuilder::new("Basic Touch").cp(18).quality(100).buff(Some((Buff::BasicTouch, 1))).build(),
-            standard_touch: ActionBuilder::new("Standard Touch").quality(125).cp(32).buff(Some((Buff::StandardTouch, 1))).build(),
-            byregot_blessing: ActionBuilder::new("Byregot's Blessing").cp(24).quality(100).buff(Some((Buff::InnerQuiet, 0))).build(),
-            prudent_touch: ActionBuilder::new("Prudent Touch").cp(25).dur(5).quality(100).build(),
-            preparatory_touch: ActionBuilder::new("Preparatory Touch").cp(40).dur(20).quality(200).buff(Some((Buff::InnerQuiet, 1))).build(),
-            advanced_touch: ActionBuilder::new("Advanced Touch").quality(150).cp(46).build(),
-            trained_finesse: ActionBuilder::new("Trained Finesse").cp(32).quality(100).dur(0).build(),
-
-            masters_mend: ActionBuilder::new("Master's Mend").cp(88).build(),
-            waste_not: ActionBuilder::new("Waste Not").cp(56).buff(Some((Buff::WasteNot, 4))).build(),
-            waste_not_ii: ActionBuilder::new("Waste Not II").cp(98).buff(Some((Buff::WasteNot, 8))).build(),
-            manipulation: ActionBuilder::new("Manipulation").cp(96).buff(Some((Buff::Manipulation, 8))).build(),
-            veneration: ActionBuilder::new("Veneration").cp(18).buff(Some((Buff::Veneration, 4))).build(),
-            great_strides: ActionBuilder::new("Great Strides").cp(32).buff(Some((Buff::GreatStrides, 3))).build(),
-            innovation: ActionBuilder::new("Innovation").cp(18).buff(Some((Buff::Innovation, 4))).build(),
-
-            // Todo: Add back
-            //observe: ActionBuilder::new("observe").cp(18).buff(Some((Buff::Observe, 1))).build(),
-            //focused_synthesis: ActionBuilder::new("focused_synthesis").cp(18).quality(100).build(),
+            muscle_memory: ActionBuilder::new(ActionId::MuscleMemory, "Muscle Memory").cp(6).progress(300).buff(Some((Buff::MuscleMemory, 5))).level(54).build(),
+            reflect: ActionBuilder::new(ActionId::Reflect, "Reflect").cp(6).quality(300).buff(Some((Buff::InnerQuiet, 1))).level(69).build(),
+
+            basic_synthesis: ActionBuilder::new(ActionId::BasicSynthesis, "Basic Synthesis").progress(120).trait_upgrade(31, 100).build(),
+            careful_synthesis: ActionBuilder::new(ActionId::CarefulSynthesis, "Careful Synthesis").cp(7).progress(180).level(62).trait_upgrade(82, 150).build(),
+            groundwork: ActionBuilder::new(ActionId::Groundwork, "Groundwork").cp(18).dur(20).progress(360).level(72).trait_upgrade(86, 300).build(),
+            prudent_synthesis: ActionBuilder::new(ActionId::PrudentSynthesis, "Prudent Synthesis").cp(18).dur(5).progress(180).level(88).build(),
+            delicate_synthesis: ActionBuilder::new(ActionId::DelicateSynthesis, "Delicate Synthesis").cp(32).progress(150).quality(100).level(76).trait_upgrade(94, 100).build(),
+            rapid_synthesis: ActionBuilder::new(ActionId::RapidSynthesis, "Rapid Synthesis").progress(500).success(50).level(9).trait_upgrade(63, 250).build(),
+            intensive_synthesis: ActionBuilder::new(ActionId::IntensiveSynthesis, "Intensive Synthesis").cp(6).progress(400).level(78).build(),
+            focused_synthesis: ActionBuilder::new(ActionId::FocusedSynthesis, "Focused Synthesis").cp(5).progress(200).success(50).level(67).build(),
+
+            basic_touch: ActionBuilder::new(ActionId::BasicTouch, "Basic Touch").cp(18).quality(100).buff(Some((Buff::BasicTouch, 1))).level(5).build(),
+            standard_touch: ActionBuilder::new(ActionId::StandardTouch, "Standard Touch").quality(125).cp(32).buff(Some((Buff::StandardTouch, 1))).level(18).build(),
+            byregot_blessing: ActionBuilder::new(ActionId::ByregotBlessing, "Byregot's Blessing").cp(24).quality(100).buff(Some((Buff::InnerQuiet, 0))).level(50).build(),
+            prudent_touch: ActionBuilder::new(ActionId::PrudentTouch, "Prudent Touch").cp(25).dur(5).quality(100).level(66).build(),
+            preparatory_touch: ActionBuilder::new(ActionId::PreparatoryTouch, "Preparatory Touch").cp(40).dur(20).quality(200).buff(Some((Buff::InnerQuiet, 1))).level(71).build(),
+            advanced_touch: ActionBuilder::new(ActionId::AdvancedTouch, "Advanced Touch").quality(150).cp(46).level(84).build(),
+            trained_finesse: ActionBuilder::new(ActionId::TrainedFinesse, "Trained Finesse").cp(32).quality(100).dur(0).level(90).build(),
+            trained_eye: ActionBuilder::new(ActionId::TrainedEye, "Trained Eye").cp(250).quality(100).level(80).build(),
+            hasty_touch: ActionBuilder::new(ActionId::HastyTouch, "Hasty Touch").quality(100).success(60).buff(Some((Buff::Expedience, 1))).level(9).build(),
+            precise_touch: ActionBuilder::new(ActionId::PreciseTouch, "Precise Touch").cp(18).quality(150).buff(Some((Buff::InnerQuiet, 1))).level(53).build(),
+            focused_touch: ActionBuilder::new(ActionId::FocusedTouch, "Focused Touch").cp(18).quality(150).success(50).level(68).build(),
+            refined_touch: ActionBuilder::new(ActionId::RefinedTouch, "Refined Touch").cp(24).quality(100).level(92).build(),
+            daring_touch: ActionBuilder::new(ActionId::DaringTouch, "Daring Touch").quality(150).level(96).build(),
+
+            masters_mend: ActionBuilder::new(ActionId::MastersMend, "Master's Mend").cp(88).level(7).build(),
+            immaculate_mend: ActionBuilder::new(ActionId::ImmaculateMend, "Immaculate Mend").cp(112).level(98).build(),
+            trained_perfection: ActionBuilder::new(ActionId::TrainedPerfection, "Trained Perfection").buff(Some((Buff::TrainedPerfection, 1))).level(100).build(),
+            waste_not: ActionBuilder::new(ActionId::WasteNot, "Waste Not").cp(56).buff(Some((Buff::WasteNot, 4))).level(15).build(),
+            waste_not_ii: ActionBuilder::new(ActionId::WasteNotII, "Waste Not II").cp(98).buff(Some((Buff::WasteNot, 8))).level(47).build(),
+            manipulation: ActionBuilder::new(ActionId::Manipulation, "Manipulation").cp(96).buff(Some((Buff::Manipulation, 8))).level(65).build(),
+            veneration: ActionBuilder::new(ActionId::Veneration, "Veneration").cp(18).buff(Some((Buff::Veneration, 4))).level(15).build(),
+            great_strides: ActionBuilder::new(ActionId::GreatStrides, "Great Strides").cp(32).buff(Some((Buff::GreatStrides, 3))).level(21).build(),
+            innovation: ActionBuilder::new(ActionId::Innovation, "Innovation").cp(18).buff(Some((Buff::Innovation, 4))).level(26).build(),
+            quick_innovation: ActionBuilder::new(ActionId::QuickInnovation, "Quick Innovation").buff(Some((Buff::Innovation, 1))).level(96).build(),
+            final_appraisal: ActionBuilder::new(ActionId::FinalAppraisal, "Final Appraisal").cp(1).buff(Some((Buff::FinalAppraisal, 5))).level(42).build(),
+
+            observe: ActionBuilder::new(ActionId::Observe, "Observe").cp(7).buff(Some((Buff::Observe, 1))).level(13).build(),
+            tricks_of_the_trade: ActionBuilder::new(ActionId::TricksOfTheTrade, "Tricks of the Trade").level(13).build(),
+            careful_observation: ActionBuilder::new(ActionId::CarefulObservation, "Careful Observation").level(55).build(),
+            heart_and_soul: ActionBuilder::new(ActionId::HeartAndSoul, "Heart and Soul").buff(Some((Buff::HeartAndSoul, 1))).level(86).build(),
+            custom: Vec::new(),
+        }
+    }
+}
+
+macro_rules! action_by_id {
+    ($list:expr, $id:expr, $($borrow:tt)+) => {
+        match $id {
+            ActionId::MuscleMemory => $($borrow)+ $list.muscle_memory,
+            ActionId::Reflect => $($borrow)+ $list.reflect,
+            ActionId::BasicSynthesis => $($borrow)+ $list.basic_synthesis,
+            ActionId::CarefulSynthesis => $($borrow)+ $list.careful_synthesis,
+            ActionId::Groundwork => $($borrow)+ $list.groundwork,
+            ActionId::PrudentSynthesis => $($borrow)+ $list.prudent_synthesis,
+            ActionId::DelicateSynthesis => $($borrow)+ $list.delicate_synthesis,
+            ActionId::BasicTouch => $($borrow)+ $list.basic_touch,
+            ActionId::StandardTouch => $($borrow)+ $list.standard_touch,
+            ActionId::ByregotBlessing => $($borrow)+ $list.byregot_blessing,
+            ActionId::PrudentTouch => $($borrow)+ $list.prudent_touch,
+            ActionId::PreparatoryTouch => $($borrow)+ $list.preparatory_touch,
+            ActionId::AdvancedTouch => $($borrow)+ $list.advanced_touch,
+            ActionId::TrainedFinesse => $($borrow)+ $list.trained_finesse,
+            ActionId::TrainedEye => $($borrow)+ $list.trained_eye,
+            ActionId::MastersMend => $($borrow)+ $list.masters_mend,
+            ActionId::WasteNot => $($borrow)+ $list.waste_not,
+            ActionId::WasteNotII => $($borrow)+ $list.waste_not_ii,
+            ActionId::Manipulation => $($borrow)+ $list.manipulation,
+            ActionId::Veneration => $($borrow)+ $list.veneration,
+            ActionId::GreatStrides => $($borrow)+ $list.great_strides,
+            ActionId::Innovation => $($borrow)+ $list.innovation,
+            ActionId::Observe => $($borrow)+ $list.observe,
+            ActionId::FocusedSynthesis => $($borrow)+ $list.focused_synthesis,
+            ActionId::FocusedTouch => $($borrow)+ $list.focused_touch,
+            ActionId::HastyTouch => $($borrow)+ $list.hasty_touch,
+            ActionId::RapidSynthesis => $($borrow)+ $list.rapid_synthesis,
+            ActionId::IntensiveSynthesis => $($borrow)+ $list.intensive_synthesis,
+            ActionId::PreciseTouch => $($borrow)+ $list.precise_touch,
+            ActionId::TricksOfTheTrade => $($borrow)+ $list.tricks_of_the_trade,
+            ActionId::FinalAppraisal => $($borrow)+ $list.final_appraisal,
+            ActionId::CarefulObservation => $($borrow)+ $list.careful_observation,
+            ActionId::HeartAndSoul => $($borrow)+ $list.heart_and_soul,
+            ActionId::ImmaculateMend => $($borrow)+ $list.immaculate_mend,
+            ActionId::TrainedPerfection => $($borrow)+ $list.trained_perfection,
+            ActionId::RefinedTouch => $($borrow)+ $list.refined_touch,
+            ActionId::DaringTouch => $($borrow)+ $list.daring_touch,
+            ActionId::QuickInnovation => $($borrow)+ $list.quick_innovation,
+            ActionId::Custom(index) => $($borrow)+ $list.custom[index as usize],
+        }
+    };
+}
+
+impl ActionList {
+    /// Get the action with the given id
+    pub fn get(&self, id: ActionId) -> &Action {
+        action_by_id!(self, id, &)
+    }
+
+    pub fn get_mut(&mut self, id: ActionId) -> &mut Action {
+        action_by_id!(self, id, &mut)
+    }
+
+    /// Iterate over all the actions of the table, the ones of the actions file last
+    pub fn iter(&self) -> impl Iterator<Item = &Action> {
+        ActionId::iter().map(move |id| self.get(id)).chain(self.custom.iter())
+    }
+
+    /// Load an action table from a toml file, see `from_config`
+    pub fn from_file(file_name: &str, base: &ActionList) -> Result<ActionList, ConfigError> {
+        Self::from_config(&config::read_file(file_name)?, file_name, base)
+    }
+
+    /// Load an action table from a parsed toml file. A table named after an action id patches the values of that
+    /// action in `base`, missing keys keep the base values. Any other table defines a new action, it must then give
+    /// the action whose special rules it follows with `rules` ("none" for a plain action)
+    pub fn from_config(config: &toml::Value, file_name: &str, base: &ActionList) -> Result<ActionList, ConfigError> {
+        let mut actions = base.clone();
+
+        for key in config.as_table().into_iter().flat_map(|t| t.keys()) {
+            let values = ConfigTable::new(config, file_name, key)?;
+            let id = match ActionId::from_str(key) {
+                Ok(id) => id,
+                Err(_) if values.values.contains_key("rules") => {
+                    let index = u8::try_from(actions.custom.len())
+                        .map_err(|_| ConfigError::UnknownTable { file: file_name.to_string(), table: key.to_string() })?;
+                    actions.custom.push(ActionBuilder::new(ActionId::Custom(index), key).rules(None).build());
+                    ActionId::Custom(index)
+                },
+                Err(_) => return Err(ConfigError::UnknownTable { file: file_name.to_string(), table: key.to_string() }),
+            };
+            let action = actions.get_mut(id);
+            let as_u32 = |field: &str| values.integer(field);
+            let as_string = |field: &str| values.optional_string(field)?.map(|v| v.to_string())
+                .ok_or(values.missing_key(field));
+
+            let mut buff = action.buff;
+            for field in values.values.keys() {
+                match field.as_str() {
+                    "name" => action.name = as_string(field)?,
+                    "short_name" => action.short_name = as_string(field)?,
+                    "cp" => action.cp = as_u32(field)?,
+                    "durability" => action.dur = as_u32(field)?,
+                    "progress" => action.progress = as_u32(field)?,
+                    "quality" => action.quality = as_u32(field)?,
+                    "success" => action.success = as_u32(field)?,
+                    "level" => action.level = as_u32(field)?,
+                    "trait_level" | "trait_progress" => {},
+                    "buff" => {
+                        let name = as_string(field)?;
+                        buff = match name.as_str() {
+                            "none" => None,
+                            _ => Some((Buff::from_str(&name).map_err(|_| values.invalid_value(field, "a buff name or 'none'"))?,
+                                       buff.map_or(1, |(_, duration)| duration))),
+                        };
+                    },
+                    "rules" => {
+                        let name = as_string(field)?;
+                        action.rules = match name.as_str() {
+                            "none" => None,
+                            _ => Some(ActionId::from_str(&name).map_err(|_| values.invalid_value(field, "an action id or 'none'"))?),
+                        };
+                    },
+                    "buff_duration" => {},
+                    _ => return Err(values.unknown_key(field)),
+                }
+            }
+            if let Some(duration) = values.optional_integer("buff_duration")? {
+                let duration = u8::try_from(duration).map_err(|_| values.invalid_value("buff_duration", "below 256"))?;
+                buff = match buff {
+                    Some((b, _)) => Some((b, duration)),
+                    None => return Err(values.invalid_value("buff_duration", "given along with a buff")),
+                };
+            }
+            action.buff = buff;
+            action.trait_upgrade = match (values.optional_integer("trait_level")?, values.optional_integer("trait_progress")?) {
+                (Some(level), Some(progress)) => Some((level, progress)),
+                (Some(_), None) => return Err(values.invalid_value("trait_level", "given along with trait_progress")),
+                (None, Some(_)) => return Err(values.invalid_value("trait_progress", "given along with trait_level")),
+                (None, None) => action.trait_upgrade,
+            };
+        }
+        Ok(actions)
+    }
+
+    /// The action table of patch 6.x, the default one follows the live game
+    pub fn endwalker() -> Self {
+        Self {
+            reflect: ActionBuilder::new(ActionId::Reflect, "Reflect").cp(6).quality(100).buff(Some((Buff::InnerQuiet, 1))).level(69).build(),
+            careful_synthesis: ActionBuilder::new(ActionId::CarefulSynthesis, "Careful Synthesis").cp(7).progress(150).level(62).trait_upgrade(82, 120).build(),
+            delicate_synthesis: ActionBuilder::new(ActionId::DelicateSynthesis, "Delicate Synthesis").cp(32).progress(100).quality(100).level(76).build(),
+            hasty_touch: ActionBuilder::new(ActionId::HastyTouch, "Hasty Touch").quality(100).success(60).level(9).build(),
+            ..Self::default()
         }
     }
 }
 
 lazy_static! {
 pub static ref ACTIONS: ActionList = ActionList::default();
-}
\ No newline at end of file
+pub static ref ACTIONS_ENDWALKER: ActionList = ActionList::endwalker();
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(content: &str) -> Result<ActionList, ConfigError> {
+        ActionList::from_config(&config::parse(content, "actions.toml")?, "actions.toml", &ACTIONS)
+    }
+
+    #[test]
+    fn patches_an_action() {
+        let actions = load("[groundwork]\ncp = 20\ntrait_level = 90\ntrait_progress = 250\n").unwrap();
+        let groundwork = actions.get(ActionId::Groundwork);
+        assert_eq!(groundwork.cp, 20);
+        assert_eq!(groundwork.progress, ACTIONS.groundwork.progress);
+        assert_eq!(groundwork.trait_upgrade, Some((90, 250)));
+        assert_eq!(groundwork.rules, Some(ActionId::Groundwork));
+    }
+
+    #[test]
+    fn rejects_half_trait_upgrades() {
+        assert!(matches!(load("[groundwork]\ntrait_level = 90\n"), Err(ConfigError::InvalidValue { key, .. }) if key == "trait_level"));
+        assert!(matches!(load("[groundwork]\ntrait_progress = 250\n"), Err(ConfigError::InvalidValue { key, .. }) if key == "trait_progress"));
+    }
+
+    #[test]
+    fn defines_an_action() {
+        let actions = load("[steady_touch]\nname = \"Steady Touch\"\nrules = \"none\"\ncp = 20\nquality = 120\n\n\
+            [hasty_step]\nrules = \"hasty_touch\"\nquality = 50\n").unwrap();
+        assert_eq!(actions.custom.len(), 2);
+        let steady = actions.custom.iter().find(|a| a.name == "Steady Touch").unwrap();
+        assert_eq!((steady.cp, steady.quality, steady.rules), (20, 120, None));
+        let hasty = actions.custom.iter().find(|a| a.name == "hasty_step").unwrap();
+        assert!(hasty.follows(ActionId::HastyTouch) && hasty != &ACTIONS.hasty_touch);
+        for action in &actions.custom {
+            assert_eq!(actions.get(action.id).name, action.name);
+        }
+        assert_eq!(actions.iter().filter(|a| matches!(a.id, ActionId::Custom(_))).count(), 2);
+    }
+
+    #[test]
+    fn rejects_unknown_tables_and_rules() {
+        assert!(matches!(load("[groundwerk]\ncp = 20\n"), Err(ConfigError::UnknownTable { .. })));
+        assert!(matches!(load("[steady_touch]\nrules = \"steady\"\n"), Err(ConfigError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn rejects_bad_buff_durations() {
+        assert!(matches!(load("[basic_synthesis]\nbuff_duration = 3\n"), Err(ConfigError::InvalidValue { .. })));
+        assert!(matches!(load("[innovation]\nbuff_duration = 300\n"), Err(ConfigError::InvalidValue { .. })));
+        let actions = load("[basic_synthesis]\nbuff = \"veneration\"\nbuff_duration = 3\n").unwrap();
+        assert_eq!(actions.basic_synthesis.buff, Some((Buff::Veneration, 3)));
+    }
+}
@@ -0,0 +1,241 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::bound::QualityBound;
+use crate::control::SearchControl;
+use crate::craft::Craft;
+use crate::io::{Objective, SearchMode, SolverResult};
+use crate::{exact, genetic, solver};
+
+/// A search algorithm, the search mode of the parameters chooses which one solves the craft
+/// It searches from the craft it is given, whose parameters hold the objective, within the limits of the control
+pub trait Solver: Send + Sync {
+    /// The routes found, ending as the game shows them, along with the counts given to the results:
+    /// the routes of the first phase or the states searched, then the routes found
+    fn search<'a>(&self, craft: Craft<'a>, control: &SearchControl) -> (Vec<Craft<'a>>, usize, usize);
+
+    /// The results of the search, see `rank_routes`
+    fn solve(&self, craft: Craft, control: &SearchControl) -> Option<Vec<SolverResult>> {
+        let objective = craft.args.objective;
+        let (routes, nb_p1, nb_p2) = self.search(craft, control);
+        rank_routes(routes, objective, nb_p1, nb_p2)
+    }
+}
+
+impl SearchMode {
+    /// The solver of the search mode
+    pub fn solver(self) -> Box<dyn Solver> {
+        match self {
+            SearchMode::Phases => Box::new(Phases),
+            SearchMode::Exact => Box::new(Exact),
+            SearchMode::Unified => Box::new(Unified),
+            SearchMode::Beam => Box::new(Beam),
+            SearchMode::Genetic => Box::new(Genetic),
+        }
+    }
+}
+
+/// Turn the routes into results, none without routes
+/// With an objective, the routes are all kept with the best one first. Without one, the routes reaching the
+/// recipe quality are kept, or all of them when none does
+pub fn rank_routes(mut routes: Vec<Craft>, objective: Option<Objective>, nb_p1: usize, nb_p2: usize) -> Option<Vec<SolverResult>> {
+    // Drop on empty results
+    if routes.is_empty() {
+        return None;
+    }
+
+    if let Some(objective) = objective {
+        routes.sort_by_key(|r| std::cmp::Reverse(objective.key(&r.state(), r.recipe.quality)));
+        return Some(routes.iter().map(|r| SolverResult::from_craft(r, nb_p1, nb_p2, r.quality >= r.recipe.quality)).collect());
+    }
+
+    // Prune the results for analysis
+    let mut valid_solutions: Vec<SolverResult> = vec![];
+    for route in routes.iter(){
+        if route.quality>=route.recipe.quality{
+            valid_solutions.push(SolverResult::from_craft(route,nb_p1,nb_p2,true));
+        }
+    }
+    // If no craft can make it to 100% HQ, fallback to base results
+    if valid_solutions.is_empty(){
+        for route in routes.iter(){valid_solutions.push(SolverResult::from_craft(route,nb_p1,nb_p2,false));}
+    }
+
+    Some(valid_solutions)
+}
+
+/// Reach the progression first then add the touches, see `SearchMode::Phases`
+pub struct Phases;
+
+/// Search every state the craft can reach, see `SearchMode::Exact`
+pub struct Exact;
+
+/// Search the progression and the touches together, see `SearchMode::Unified`
+pub struct Unified;
+
+/// Keep the best crafts of each step, see `SearchMode::Beam`
+pub struct Beam;
+
+/// Evolve action sequences from a seed, see `SearchMode::Genetic`
+pub struct Genetic;
+
+/// Steps up to which each craft of the first phase is searched as a task of its own, so the idle threads can steal them
+const PHASE1_SPLIT_STEPS: u32 = 6;
+
+impl Solver for Phases {
+    /// Both phases run on a work stealing pool: the first phase is split in tasks, and each of its routes is searched
+    /// by a task of the second phase as soon as it is found. The results are kept in a buffer per thread
+    fn search<'a>(&self, craft: Craft<'a>, control: &SearchControl) -> (Vec<Craft<'a>>, usize, usize) {
+        let params = craft.args.clone();
+        // No thread count uses every core
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(params.threads).build().unwrap();
+        let buffers = (0..pool.current_num_threads()).map(|_| Mutex::new(Vec::<Craft>::new())).collect::<Vec<_>>();
+        let bound = QualityBound::new(&craft);
+        let shared = solver::SharedBest::default();
+        let nb_p1 = AtomicUsize::new(0);
+
+        #[cfg(feature = "verbose")]
+        if params.verbose>0{
+            println!("[P1] Starting phase 1, the routes are tested as they are found...");
+        }
+
+        let tasks = PhaseTasks { buffers: &buffers, bound: &bound, shared: &shared, control, nb_p1: &nb_p1 };
+        pool.scope(|scope| tasks.phase1(scope, craft));
+
+        let mut phase2_routes = buffers.into_iter().flat_map(|buffer| buffer.into_inner().unwrap()).collect::<Vec<_>>();
+        // The order the threads finished in is left out, so the routes kept on ties don't depend on it
+        phase2_routes.sort_by_cached_key(|r| r.actions.iter().map(|a| a.id).collect::<Vec<_>>());
+        let nb_p1 = nb_p1.into_inner();
+        let nb_p2 = phase2_routes.len();
+
+        // Print the results if verbose
+        #[cfg(feature = "verbose")]
+        if params.verbose>0{
+            println!("[P1] Found {} routes",nb_p1);
+            println!("[P2] Found {} solutions, sorting",phase2_routes.len());
+            if params.verbose>1{ for r in phase2_routes.iter(){
+                    println!("[P2] {:?} p:{}% q:{}% d:{}", r.actions, r.progression * 100 / r.recipe.progress, r.quality * 100 / r.recipe.quality, r.durability);
+            }}
+        }
+
+        (phase2_routes, nb_p1, nb_p2)
+    }
+}
+
+/// What the tasks of the phases share
+#[derive(Clone, Copy)]
+struct PhaseTasks<'s, 'a> {
+    buffers: &'s [Mutex<Vec<Craft<'a>>>],
+    bound: &'s QualityBound,
+    shared: &'s solver::SharedBest,
+    control: &'s SearchControl,
+    nb_p1: &'s AtomicUsize,
+}
+
+impl<'s, 'a: 's> PhaseTasks<'s, 'a> {
+    /// Search the first phase from the craft, the crafts reaching the split steps are spawned as new tasks
+    fn phase1(self, scope: &rayon::Scope<'s>, craft: Craft<'a>) {
+        // The tasks still queued after the stop spawn nothing more
+        if self.control.is_stopped() {
+            return;
+        }
+        let split_step = (craft.step_count < PHASE1_SPLIT_STEPS).then_some(craft.step_count + 1);
+        let (routes, split) = solver::generate_routes_phase1(craft, split_step, self.control);
+        for craft in split {
+            scope.spawn(move |scope| self.phase1(scope, craft));
+        }
+        self.nb_p1.fetch_add(routes.len(), Ordering::Relaxed);
+        self.control.add_phase1_routes(routes.len());
+        for route in routes {
+            #[cfg(feature = "verbose")]
+            if route.args.verbose>1{
+                println!("[P1] {:?} p:{}% q:{}% c:{} d:{}",route.actions,route.progression,route.quality * 100 / route.recipe.quality,route.cp,route.durability);
+            }
+            scope.spawn(move |_| self.phase2(route));
+        }
+    }
+
+    /// Fill the route with the best touches, the results go to the buffer of the thread
+    fn phase2(self, route: Craft<'a>) {
+        if let Some(mut routes) = solver::generate_routes_phase2(route, self.bound, self.shared, self.control) {
+            let thread = rayon::current_thread_index().unwrap();
+            self.buffers[thread].lock().unwrap().append(&mut routes);
+        }
+        self.control.phase1_route_done();
+    }
+}
+
+impl Solver for Exact {
+    fn search<'a>(&self, craft: Craft<'a>, control: &SearchControl) -> (Vec<Craft<'a>>, usize, usize) {
+        #[cfg(feature = "verbose")]
+        let verbose = craft.args.verbose;
+        #[cfg(feature = "verbose")]
+        if verbose>0{
+            println!("[E] Searching all the states...");
+        }
+        let mut solver = exact::ExactSolver::new(&craft, control);
+        let routes = solver.solve(craft).into_iter().collect::<Vec<_>>();
+        #[cfg(feature = "verbose")]
+        if verbose>0{
+            println!("[E] Searched {} states",solver.nb_states);
+        }
+        let nb_routes = routes.len();
+        (routes, solver.nb_states, nb_routes)
+    }
+}
+
+impl Solver for Unified {
+    fn search<'a>(&self, craft: Craft<'a>, control: &SearchControl) -> (Vec<Craft<'a>>, usize, usize) {
+        #[cfg(feature = "verbose")]
+        let verbose = craft.args.verbose;
+        #[cfg(feature = "verbose")]
+        if verbose>0{
+            println!("[U] Searching the progression and the touches together...");
+        }
+        let bound = QualityBound::new(&craft);
+        let (routes, nb_states) = solver::generate_routes_unified(craft, &bound, control);
+        #[cfg(feature = "verbose")]
+        if verbose>0{
+            println!("[U] Searched {} states, found {} routes",nb_states,routes.len());
+        }
+        let nb_routes = routes.len();
+        (routes, nb_states, nb_routes)
+    }
+}
+
+impl Solver for Beam {
+    fn search<'a>(&self, craft: Craft<'a>, control: &SearchControl) -> (Vec<Craft<'a>>, usize, usize) {
+        #[cfg(feature = "verbose")]
+        let verbose = craft.args.verbose;
+        #[cfg(feature = "verbose")]
+        if verbose>0{
+            println!("[B] Keeping the {} best crafts of each step...",craft.args.beam_width);
+        }
+        let bound = QualityBound::new(&craft);
+        let (routes, nb_states) = solver::generate_routes_beam(craft, &bound, control);
+        #[cfg(feature = "verbose")]
+        if verbose>0{
+            println!("[B] Searched {} states, found {} routes",nb_states,routes.len());
+        }
+        let nb_routes = routes.len();
+        (routes, nb_states, nb_routes)
+    }
+}
+
+impl Solver for Genetic {
+    fn search<'a>(&self, craft: Craft<'a>, control: &SearchControl) -> (Vec<Craft<'a>>, usize, usize) {
+        #[cfg(feature = "verbose")]
+        let verbose = craft.args.verbose;
+        #[cfg(feature = "verbose")]
+        if verbose>0{
+            println!("[G] Evolving {} rotations over {} generations from seed {}...",craft.args.population,craft.args.generations,craft.args.seed);
+        }
+        let mut solver = genetic::GeneticSolver::new(&craft, control);
+        let routes = solver.solve();
+        #[cfg(feature = "verbose")]
+        if verbose>0{
+            println!("[G] Evaluated {} rotations, kept {} routes",solver.nb_rotations,routes.len());
+        }
+        let nb_routes = routes.len();
+        (routes, solver.nb_rotations, nb_routes)
+    }
+}
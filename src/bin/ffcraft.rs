@@ -1,22 +1,121 @@
 // #![warn(missing_docs,unsafe_code,unstable_features,)]
-use std::time::{Instant};
+use std::time::{Duration, Instant};
 use clap::Parser;
 use xiv_craft_solver;
+use xiv_craft_solver::control::{CancelToken, Progress, ProgressCallback, SearchControl};
+use xiv_craft_solver::io::{Command, RecipesCommand};
+use xiv_craft_solver::recipe_db::RecipeDatabase;
+use xiv_craft_solver::recipe_level;
 
 #[derive(Debug)]
 struct CustomError(String);
 
+/// Print the error and stop with a non-zero exit code
+fn exit_with_error(error: impl std::fmt::Display) -> ! {
+    eprintln!("[Error] {}", error);
+    std::process::exit(1)
+}
+
 fn main() {
     let args = xiv_craft_solver::io::Args::parse();
-    let params = xiv_craft_solver::io::Parameters::from_args(&args);
+    let mut params = xiv_craft_solver::io::Parameters::from_args(&args);
+    if let Some(file_name) = &args.actions_file {
+        match xiv_craft_solver::load_actions_file(file_name, args.game_version) {
+            Ok(actions) => params.action_table = Some(actions),
+            Err(e) => exit_with_error(e),
+        }
+    }
+
+    let recipe_db = match &args.recipe_db {
+        Some(directory) => match RecipeDatabase::from_directory(directory) {
+            Ok(db) => Some(db),
+            Err(e) => exit_with_error(e),
+        },
+        None => None,
+    };
+
+    if let Some(Command::Recipes { command }) = &args.command {
+        let db = match &recipe_db {
+            Some(db) => db,
+            None => exit_with_error("Browsing recipes needs a recipe database, give it with --recipe-db"),
+        };
+        match command {
+            RecipesCommand::Search { text } => for entry in db.search(text).iter().take(20) {
+                println!("{} [{}] rlvl {} | Progress: {} | Quality: {} | Durability: {}{}", entry.name, entry.job,
+                    entry.recipe.rlvl, entry.recipe.progress, entry.recipe.quality, entry.recipe.durability,
+                    if entry.recipe.expert { " | Expert" } else { "" });
+            },
+            RecipesCommand::Levels => {
+                println!("{}", recipe_level::CSV_HEADER);
+                for level in &db.levels {
+                    println!("{}", level.to_csv_line());
+                }
+            },
+        }
+        return
+    }
 
     // Start timer
     let now = Instant::now();
     println!("Solving...");
 
-    // Solve from config
-    let (recipe,stats) = xiv_craft_solver::load_from_config(&args.recipe_name, &args.file_name, &args.character_name);
-    let results = xiv_craft_solver::solve_craft(recipe,stats,params);
+    // Solve from config, or from the recipe database when given one
+    let (recipe,stats) = match &recipe_db {
+        Some(db) => match db.find(&args.recipe_name) {
+            Some(entry) => {
+                println!("Using recipe '{}' [{}]", entry.name, entry.job);
+                match xiv_craft_solver::load_stats_from_config(&args.character_name, &args.file_name) {
+                    Ok(stats) => (entry.recipe, stats),
+                    Err(e) => exit_with_error(e),
+                }
+            },
+            None => exit_with_error(format!("Can't find a recipe named '{}' in the recipe database", args.recipe_name)),
+        },
+        None => match xiv_craft_solver::load_from_config(&args.recipe_name, &args.file_name, &args.character_name) {
+            Ok(config) => config,
+            Err(e) => exit_with_error(e),
+        },
+    };
+    // Show the progress of the search when verbose
+    let progress: Option<ProgressCallback> = match args.verbose {
+        0 => None,
+        _ => Some(Box::new(|p: &Progress| println!("[Progress] Phase 1 routes: {}/{} | Nodes: {} | Best quality: {}",
+            p.phase1_done, p.phase1_routes, p.nodes, p.best_quality))),
+    };
+    let control = SearchControl::new(args.time_limit.map(Duration::from_secs_f64), CancelToken::default(), progress);
+
+    // The policy replaces the routes, its decision table is written to the file
+    if let Some(file_name) = &args.policy {
+        let policy = xiv_craft_solver::solve_policy(recipe, stats, &params, &control);
+        if !policy.complete {
+            println!("[Warning] The search ran out of time, the states it didn't search are only finished with the synthesis");
+        }
+        let written = std::fs::File::create(file_name)
+            .and_then(|file| policy.write_csv(std::io::BufWriter::new(file)));
+        if let Err(e) = written {
+            exit_with_error(format!("Can't write the policy to '{}': {}", file_name, e));
+        }
+        println!("\n > POLICY [{} states] <\nExpected quality: [{:.0}/{}] | Decision table written to '{}'",
+            policy.nb_states, policy.expected_quality, recipe.quality, file_name);
+        println!("\nProgram finished successfully in {}ms\nPress enter to exit...", now.elapsed().as_millis());
+        let mut input = String::new(); std::io::stdin().read_line(&mut input).unwrap();
+        return
+    }
+
+    let results = match args.stream {
+        true => {
+            let mut stream = xiv_craft_solver::solve_craft_stream(recipe, stats, params.clone(), control.clone());
+            for result in stream.by_ref() {
+                println!("\n > FOUND [{}ms] <", now.elapsed().as_millis());
+                result.pretty_print();
+            }
+            stream.join()
+        },
+        false => xiv_craft_solver::solve_craft_with(recipe,stats,params.clone(),&control),
+    };
+    if control.is_stopped() {
+        println!("[Warning] The search ran out of time, showing the best routes found so far");
+    }
     
     // Stop timer
     let t_final = now.elapsed().as_millis();
@@ -40,12 +139,29 @@ fn main() {
             }
         },
     }
-    println!("\n > SOLUTION [Least steps] <");
-    xiv_craft_solver::find_fast_route(&results).unwrap().pretty_print();
-    println!("\n > SOLUTION [Most durability] <");
-    xiv_craft_solver::find_safe_route(&results).unwrap().pretty_print();
-    println!("\n > SOLUTION [Most quality] < ");
-    xiv_craft_solver::find_quality_route(&results).unwrap().pretty_print();
+    match params.objective {
+        _ if args.pareto => {
+            let front = xiv_craft_solver::find_pareto_routes(&results);
+            println!("\n > PARETO FRONT [{} routes] <", front.len());
+            for route in front {
+                println!();
+                route.pretty_print();
+            }
+        },
+        // The solver already put the best route for the objective first
+        Some(objective) => {
+            println!("\n > SOLUTION [{}] <", objective);
+            results.as_ref().unwrap()[0].pretty_print();
+        },
+        None => {
+            println!("\n > SOLUTION [Least steps] <");
+            xiv_craft_solver::find_fast_route(&results).unwrap().pretty_print();
+            println!("\n > SOLUTION [Most durability] <");
+            xiv_craft_solver::find_safe_route(&results).unwrap().pretty_print();
+            println!("\n > SOLUTION [Most quality] < ");
+            xiv_craft_solver::find_quality_route(&results).unwrap().pretty_print();
+        },
+    }
 
     // #[cfg(feature = "verbose")]
     // if params.verbose>2{
@@ -0,0 +1,456 @@
+use crate::action::{Action, ActionId};
+use crate::craft::{Craft, CraftState};
+use crate::specs::{Buff, BuffState, Condition};
+
+/// The one step combos of the touches
+const COMBOS: usize = 4;
+/// Heart and Soul and Quick Innovation still usable
+const HEART_AND_SOUL: usize = 1;
+const QUICK_INNOVATION: usize = 2;
+/// Parts of the durability price each relaxation pays, the cp alone then more and more of the durability
+const DURABILITY_WEIGHTS: [f64; 4] = [0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0];
+
+/// A move of the relaxed craft, from one buff state to another
+#[derive(Debug, Clone, Copy)]
+struct Move {
+    cp: u32,
+    durability: u32,
+    /// Durability under Waste Not
+    waste_not_durability: u32,
+    /// Most progression the move can give
+    progress: u32,
+    /// Whether it uses up a one time action
+    one_time: bool,
+    quality: u32,
+    next: usize,
+}
+
+/// The craft relaxed to a single budget, its durability being paid with cp at some price
+///
+/// A route spends less than its cp plus its durability at any price up to the cheapest one the durability
+/// can be bought back with, so the best quality of every relaxation bounds the one of the craft.
+struct Relaxation {
+    /// Cp paid for one durability point
+    durability_price: f64,
+    /// Price of the cheapest synthesis, and the cheapest price of one progression point
+    synthesis_price: f64,
+    progress_price: f64,
+    max_budget: usize,
+    /// Best quality gain for each budget then relaxed state
+    best: Vec<u32>,
+}
+
+/// An upper bound of the quality a craft can still gain with the actions that can't fail
+///
+/// The craft is relaxed to its quality part: durability is turned into cp, each step can halve its durability
+/// for its part of the cp of Waste Not, the progression only holds back the cp of the synthesis still needed,
+/// and the buffs that aren't for quality are dropped. The best quality of the relaxed craft is found once
+/// for every budget, inner quiet, innovation, great strides, combo and one time action still usable.
+/// With the conditions, each step of the relaxed craft is under the one best for it: the least cp and durability
+/// (Pliant, Sturdy), the longest buffs (Primed), the most progression (Malleable) and quality (Good, Excellent).
+pub struct QualityBound {
+    /// Cp of Waste Not for one step
+    waste_not_price: Option<f64>,
+    /// Most durability saved by Trained Perfection
+    max_durability_cost: f64,
+    trained_perfection: bool,
+    /// Most progression a synthesis can give
+    synthesis_progress: u32,
+    /// Most durability of a synthesis, the last one can break the item
+    synthesis_durability: i32,
+    tricks_cp: f64,
+    inner_quiet: usize,
+    innovation: usize,
+    great_strides: usize,
+    flags: usize,
+    specialist_actions: (bool, bool),
+    relaxations: Vec<Relaxation>,
+}
+
+impl QualityBound {
+    /// The bound of the crafts run under the Normal condition, like the searches simulate them
+    pub fn new(craft: &Craft) -> Self {
+        Self::relaxed(craft, &[Condition::Normal])
+    }
+
+    /// The bound of the crafts whose conditions are rolled by the game, each step being under its best condition
+    pub fn with_conditions(craft: &Craft) -> Self {
+        Self::relaxed(craft, craft.recipe.conditions())
+    }
+
+    fn relaxed(craft: &Craft, conditions: &[Condition]) -> Self {
+        let actions = craft.action_list();
+        let mut scratch = craft.clone();
+        scratch.actions.clear();
+        scratch.condition = Condition::Normal;
+        scratch.step_count = 1;
+        let available = |a: &Action| craft.args.game_version.has_action(a.id) && craft.stats.level >= a.level;
+        let buff_bonus = conditions.iter().map(|c| c.buff_bonus()).max().unwrap_or(0);
+        let duration = |a: &Action| a.buff.map_or(0.0, |(buff, d)| (d + if buff.is_timed() { buff_bonus } else { 0 }) as f64);
+        // The cp of an action under each condition, with the steps of its buff
+        let cp_of = |a: &Action| conditions.iter().map(|&condition| {
+            let mut scratch = scratch.clone();
+            scratch.condition = condition;
+            let steps = a.buff.map_or(0, |(buff, d)| d + if buff.is_timed() { condition.buff_bonus() } else { 0 });
+            (a.get_cp_cost(&scratch) as f64, steps as f64)
+        }).collect::<Vec<_>>();
+
+        // The cheapest durability, Manipulation and the mends give it back at some cp price
+        let mut prices = Vec::new();
+        if available(&actions.manipulation) {
+            prices.extend(cp_of(&actions.manipulation).into_iter().map(|(cp, steps)| cp / (5.0 * steps)));
+        }
+        for mend in [&actions.masters_mend, &actions.immaculate_mend] {
+            let restored = mend.get_durability_restore(craft) as f64;
+            if available(mend) && restored > 0.0 { prices.extend(cp_of(mend).into_iter().map(|(cp, _)| cp / restored)); }
+        }
+        // Without any, durability can't be bought and any price keeps the bound
+        let durability_price = prices.into_iter().reduce(f64::min).unwrap_or(1.0);
+        let waste_not_price = [&actions.waste_not, &actions.waste_not_ii].into_iter()
+            .filter(|a| available(a))
+            .flat_map(|a| cp_of(a).into_iter().map(|(cp, steps)| cp / steps))
+            .reduce(f64::min);
+
+        let mut boosted = scratch.clone();
+        boosted.buffs = BuffState { muscle_memory: 1, veneration: 1, ..BuffState::default() };
+        boosted.durability = i32::MAX / 2;
+        boosted.condition = conditions.iter().copied().max_by(|a, b| a.progress_multiplier().total_cmp(&b.progress_multiplier())).unwrap_or(Condition::Normal);
+        let progress_of = |a: &Action| (a.get_progress(&boosted) as f64 * craft.get_base_progression() as f64 / 100.0) as u32;
+        // The least durability of an action, without then with Waste Not
+        let durability_of = |a: &Action| conditions.iter().map(|&condition| {
+            let mut scratch = scratch.clone();
+            scratch.condition = condition;
+            let full = a.get_durability_cost(&scratch);
+            scratch.buffs.waste_not = 1;
+            (full, a.get_durability_cost(&scratch))
+        }).fold((u32::MAX, u32::MAX), |(full, halved), (f, h)| (full.min(f), halved.min(h)));
+        let synthesis = actions.iter().filter(|a| available(a) && a.progress > 0).collect::<Vec<_>>();
+        let synthesis_progress = synthesis.iter().map(|a| progress_of(a)).max().unwrap_or(0);
+        let specialist_actions = (craft.stats.specialist && available(&actions.heart_and_soul),
+                                  craft.stats.specialist && available(&actions.quick_innovation));
+
+        let mut bound = Self {
+            waste_not_price,
+            max_durability_cost: actions.iter().filter(|a| a.uses_durability()).map(|a| a.dur).max().unwrap_or(0) as f64,
+            trained_perfection: available(&actions.trained_perfection),
+            synthesis_progress,
+            synthesis_durability: synthesis.iter().filter(|a| a.uses_durability()).map(|a| a.dur).max().unwrap_or(0) as i32,
+            tricks_cp: if available(&actions.tricks_of_the_trade) { 20.0 } else { 0.0 },
+            inner_quiet: 11,
+            innovation: duration(&actions.innovation).max(duration(&actions.quick_innovation)) as usize + 1,
+            great_strides: duration(&actions.great_strides) as usize + 1,
+            flags: if specialist_actions.1 { 4 } else if specialist_actions.0 { 2 } else { 1 },
+            specialist_actions,
+            relaxations: Vec::new(),
+        };
+
+        // The synthesis still needed are paid first, at the price of the cheapest one
+        // and with the most progression one can give, or at the cheapest price of their progression
+        for weight in DURABILITY_WEIGHTS {
+            let mut relaxation = Relaxation {
+                durability_price: weight * durability_price,
+                synthesis_price: 0.0,
+                progress_price: 0.0,
+                max_budget: 0,
+                best: Vec::new(),
+            };
+            let prices = synthesis.iter()
+                .map(|a| {
+                    let cp = cp_of(a).into_iter().map(|(cp, _)| cp).reduce(f64::min).unwrap_or(0.0);
+                    let (full, halved) = durability_of(a);
+                    (cp + if a.uses_durability() { relaxation.durability_cost(full, halved, waste_not_price) } else { 0.0 }, progress_of(a))
+                })
+                .collect::<Vec<_>>();
+            relaxation.synthesis_price = prices.iter().map(|&(price, _)| price).reduce(f64::min).unwrap_or(0.0);
+            relaxation.progress_price = prices.iter()
+                .filter(|&&(_, progress)| progress > 0)
+                .map(|&(price, progress)| price / progress as f64)
+                .reduce(f64::min).unwrap_or(0.0);
+            bound.relaxations.push(relaxation);
+        }
+
+        // The actions that help the quality, the synthesis they save is held back from their cost
+        let quality_actions = actions.iter()
+            .filter(|a| available(a) && a.id != ActionId::HeartAndSoul)
+            .filter(|a| a.quality > 0 || matches!(a.buff, Some((Buff::Innovation | Buff::GreatStrides | Buff::Observe, _))))
+            .collect::<Vec<_>>();
+        let nb_states = bound.nb_states();
+        let mut moves = vec![Vec::new(); nb_states];
+        for (state, state_moves) in moves.iter_mut().enumerate() {
+            let (buffs, flags) = bound.buffs_of(state);
+            for (&action, &condition) in quality_actions.iter().flat_map(|a| conditions.iter().map(move |c| (a, c))) {
+                scratch.buffs = buffs;
+                scratch.condition = condition;
+                scratch.cp = i32::MAX / 2;
+                scratch.durability = i32::MAX / 2;
+                scratch.recipe.durability = u32::MAX / 2;
+                scratch.quality = 0;
+                scratch.progression = 0;
+                scratch.recipe.progress = u32::MAX / 2;
+                if !action.can_use(&scratch) || action.get_success_rate(&scratch) < 100 {
+                    continue;
+                }
+                let cp = action.get_cp_cost(&scratch);
+                let durability = action.get_durability_cost(&scratch);
+                scratch.buffs.waste_not = 1;
+                let waste_not_durability = action.get_durability_cost(&scratch);
+                scratch.buffs.waste_not = 0;
+                scratch.actions.clear();
+                scratch.run_action(action, condition);
+                let mut next_flags = flags;
+                if action.needs_good_condition() && !matches!(condition, Condition::Good | Condition::Excellent) {
+                    next_flags &= !HEART_AND_SOUL;
+                }
+                if action.id == ActionId::QuickInnovation { next_flags &= !QUICK_INNOVATION; }
+                state_moves.push(Move {
+                    cp,
+                    durability,
+                    waste_not_durability,
+                    progress: progress_of(action),
+                    one_time: next_flags != flags,
+                    quality: scratch.quality,
+                    next: bound.state_of(&scratch.buffs, next_flags),
+                });
+            }
+        }
+
+        // Best quality of every relaxation for every budget
+        let full_buffs = BuffState {
+            manipulation: duration(&actions.manipulation) as u8,
+            waste_not: duration(&actions.waste_not_ii).max(duration(&actions.waste_not)) as u8,
+            trained_perfection: 1,
+            ..BuffState::default()
+        };
+        let mut relaxations = std::mem::take(&mut bound.relaxations);
+        for relaxation in &mut relaxations {
+            relaxation.max_budget = bound.budget(relaxation, craft.stats.max_cp as i32, craft.recipe.durability as i32, &full_buffs, bound.flags - 1).max(0) as usize;
+            relaxation.best = best_gains(&moves, relaxation.max_budget, |m| relaxation.move_cost(m, waste_not_price));
+        }
+        bound.relaxations = relaxations;
+        bound
+    }
+
+    fn nb_states(&self) -> usize {
+        self.inner_quiet * self.innovation * self.great_strides * COMBOS * self.flags
+    }
+
+    /// Index of the relaxed state, the one time actions come first so they are computed before the ones using them
+    fn state_of(&self, buffs: &BuffState, flags: usize) -> usize {
+        let combo = if buffs.basic_touch > 0 { 1 } else if buffs.standard_touch > 0 { 2 } else if buffs.observe > 0 { 3 } else { 0 };
+        let index = (buffs.inner_quiet as usize).min(self.inner_quiet - 1);
+        let index = index * self.innovation + (buffs.innovation as usize).min(self.innovation - 1);
+        let index = index * self.great_strides + (buffs.great_strides as usize).min(self.great_strides - 1);
+        let index = index * COMBOS + combo;
+        flags * (self.nb_states() / self.flags) + index
+    }
+
+    fn buffs_of(&self, state: usize) -> (BuffState, usize) {
+        let per_flag = self.nb_states() / self.flags;
+        let (flags, index) = (state / per_flag, state % per_flag);
+        let combo = index % COMBOS;
+        let index = index / COMBOS;
+        let great_strides = index % self.great_strides;
+        let index = index / self.great_strides;
+        let innovation = index % self.innovation;
+        let inner_quiet = index / self.innovation;
+        (BuffState {
+            inner_quiet: inner_quiet as u8,
+            innovation: innovation as u8,
+            great_strides: great_strides as u8,
+            basic_touch: (combo == 1) as u8,
+            standard_touch: (combo == 2) as u8,
+            observe: (combo == 3) as u8,
+            heart_and_soul: (flags & HEART_AND_SOUL > 0) as u8,
+            quick_innovation_used: (flags & QUICK_INNOVATION == 0) as u8,
+            ..BuffState::default()
+        }, flags)
+    }
+
+    /// The flags of the one time actions the craft can still use
+    fn flags_of(&self, buffs: &BuffState) -> usize {
+        let mut flags = 0;
+        if self.specialist_actions.0 && (buffs.heart_and_soul > 0 || buffs.heart_and_soul_used == 0) { flags |= HEART_AND_SOUL; }
+        if self.specialist_actions.1 && buffs.quick_innovation_used == 0 { flags |= QUICK_INNOVATION; }
+        flags
+    }
+
+    /// The budget of a relaxation, with the durability and the durability buffs turned into cp
+    fn budget(&self, relaxation: &Relaxation, cp: i32, durability: i32, buffs: &BuffState, flags: usize) -> i64 {
+        let durability = durability as f64 + 5.0 * buffs.manipulation as f64
+            + if buffs.trained_perfection > 0 || (self.trained_perfection && buffs.trained_perfection_used == 0) {
+                self.max_durability_cost
+            } else { 0.0 };
+        let waste_not = match self.waste_not_price {
+            Some(price) if relaxation.durability_price > 0.0 => price * buffs.waste_not as f64,
+            _ => 0.0,
+        };
+        let tricks = if flags & HEART_AND_SOUL > 0 { self.tricks_cp } else { 0.0 };
+        (cp as f64 + tricks + waste_not + durability * relaxation.durability_price).ceil() as i64
+    }
+
+    /// The most quality the craft can end with, holding back the cp of the synthesis for the missing progression
+    /// The first step isn't bounded since it has its own actions
+    pub fn max_quality(&self, state: &CraftState, missing_progress: u32) -> u32 {
+        if state.step_count == 0 {
+            return u32::MAX;
+        }
+        let flags = self.flags_of(&state.buffs);
+        let index = self.state_of(&state.buffs, flags);
+        let nb_states = self.nb_states();
+        // The synthesis finishing the craft only needs one durability point before it
+        let durability = match missing_progress {
+            0 => state.durability,
+            _ => state.durability + self.synthesis_durability - 1,
+        };
+        let mut gain = u32::MAX;
+        for relaxation in &self.relaxations {
+            let synthesis = missing_progress.div_ceil(self.synthesis_progress.max(1)) as f64;
+            let synthesis_cp = (synthesis * relaxation.synthesis_price).max(missing_progress as f64 * relaxation.progress_price);
+            let budget = self.budget(relaxation, state.cp, durability, &state.buffs, flags) - synthesis_cp.floor() as i64;
+            if budget < 0 {
+                return state.quality;
+            }
+            let budget = (budget as usize).min(relaxation.max_budget);
+            gain = gain.min(relaxation.best[budget * nb_states + index]);
+        }
+        state.quality + gain
+    }
+}
+
+impl Relaxation {
+    /// The cp of a durability cost, with the part of Waste Not when it is worth it
+    fn durability_cost(&self, durability: u32, waste_not_durability: u32, waste_not_price: Option<f64>) -> f64 {
+        let full = self.durability_price * durability as f64;
+        match waste_not_price {
+            Some(price) if self.durability_price > 0.0 => full.min(self.durability_price * waste_not_durability as f64 + price),
+            _ => full,
+        }
+    }
+
+    fn move_cost(&self, m: &Move, waste_not_price: Option<f64>) -> usize {
+        let mut cost = m.cp as f64 + if m.durability > 0 { self.durability_cost(m.durability, m.waste_not_durability, waste_not_price) } else { 0.0 };
+        if m.progress > 0 {
+            // The synthesis saved, a whole one or the price of its progression
+            cost -= self.synthesis_price.max(self.progress_price * m.progress as f64);
+        }
+        let cost = cost.max(0.0).floor() as usize;
+        // Moves that cost nothing must use a one time action, or the bound would loop on them
+        if cost == 0 && !m.one_time { 1 } else { cost }
+    }
+}
+
+/// Best quality gain for every amount of budget then relaxed state,
+/// the moves go to a lower amount or use up a one time action
+fn best_gains(moves: &[Vec<Move>], max: usize, cost: impl Fn(&Move) -> usize) -> Vec<u32> {
+    let nb_states = moves.len();
+    // The costs don't depend on the budget, they are found once
+    let moves = moves.iter()
+        .map(|state_moves| state_moves.iter().map(|m| (cost(m), m.quality, m.next)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let mut best = vec![0; (max + 1) * nb_states];
+    for amount in 0..=max {
+        for (state, state_moves) in moves.iter().enumerate() {
+            let mut gain = 0;
+            for &(cost, quality, next) in state_moves {
+                if cost <= amount {
+                    gain = gain.max(quality + best[(amount - cost) * nb_states + next]);
+                }
+            }
+            best[amount * nb_states + state] = gain;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::craft::fixtures::params;
+    use crate::specs::Success;
+    use super::*;
+
+    fn craft<'a>(durability: u32, progress: u32, max_cp: u32) -> Craft<'a> {
+        // The quality is out of reach, so every quality point counts
+        crate::craft::fixtures::craft(durability, progress, 100000, max_cp, params())
+    }
+
+    /// The most quality a finished route can reach from a state, trying every usable action that can't fail
+    /// under each of the conditions, but Tricks of the Trade whose cp the bound leaves out
+    fn reachable(craft: &mut Craft, state: CraftState, conditions: &[Condition], seen: &mut HashMap<CraftState, Option<u32>>) -> Option<u32> {
+        if let Some(&quality) = seen.get(&state) {
+            return quality;
+        }
+        let mut best = None;
+        for action in craft.action_list().iter().filter(|a| !matches!(a.id, ActionId::CarefulObservation | ActionId::TricksOfTheTrade)) {
+            for &condition in conditions {
+                craft.set_state(state);
+                craft.condition = condition;
+                if !action.can_use(craft) || action.get_success_rate(craft) < 100 {
+                    continue;
+                }
+                craft.actions.clear();
+                craft.run_action(action, condition);
+                let child = craft.state();
+                let quality = match child.success {
+                    Success::Success => Some(child.quality),
+                    Success::Failure => None,
+                    Success::Pending => reachable(craft, child, conditions, seen),
+                };
+                best = best.max(quality);
+            }
+        }
+        seen.insert(state, best);
+        best
+    }
+
+    #[test]
+    fn bounds_the_quality_reached() {
+        let openers: [&[ActionId]; 4] = [
+            &[ActionId::Reflect],
+            &[ActionId::MuscleMemory, ActionId::Innovation],
+            &[ActionId::BasicTouch, ActionId::StandardTouch],
+            &[ActionId::TrainedPerfection, ActionId::BasicTouch, ActionId::BasicSynthesis],
+        ];
+        for (durability, progress, max_cp) in [(20, 600, 40), (30, 900, 45), (40, 300, 40)] {
+            let mut craft = craft(durability, progress, max_cp);
+            let bound = QualityBound::new(&craft);
+            let start = craft.state();
+            let mut seen = HashMap::new();
+            for opener in openers {
+                craft.set_state(start);
+                for &id in opener {
+                    if craft.success != Success::Pending {
+                        break;
+                    }
+                    let action = craft.action_list().get(id);
+                    assert!(action.can_use(&craft));
+                    craft.run_action(action, Condition::Normal);
+                }
+                let state = craft.state();
+                if state.success != Success::Pending {
+                    continue;
+                }
+                let max_quality = bound.max_quality(&state, progress - state.progression);
+                if let Some(quality) = reachable(&mut craft, state, &[Condition::Normal], &mut seen) {
+                    assert!(max_quality >= quality, "{:?} after {:?}: bound {} under {}", (durability, progress, max_cp), opener, max_quality, quality);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bounds_the_quality_reached_under_the_conditions() {
+        let mut craft = craft(20, 600, 24);
+        craft.recipe.expert = true;
+        let bound = QualityBound::with_conditions(&craft);
+        // The first step isn't bounded, the bound starts after a synthesis
+        craft.run_action(&craft.action_list().basic_synthesis, Condition::Normal);
+        let state = craft.state();
+        let missing_progress = craft.recipe.progress - state.progression;
+        let conditions = craft.recipe.conditions();
+        let quality = reachable(&mut craft, state, conditions, &mut HashMap::new()).unwrap();
+        assert!(bound.max_quality(&state, missing_progress) >= quality);
+        // The conditions give more quality than the Normal one allows
+        assert!(QualityBound::new(&craft).max_quality(&state, missing_progress) < quality);
+    }
+}
@@ -0,0 +1,110 @@
+use std::fmt::{Display, Formatter};
+
+/// What went wrong while reading a toml config file
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file can't be read
+    Io { file: String, error: std::io::Error },
+    /// The file isn't valid toml
+    Parse { file: String, error: toml::de::Error },
+    /// The recipe, character or action isn't in the file
+    MissingTable { file: String, table: String },
+    /// The table doesn't correspond to anything known
+    UnknownTable { file: String, table: String },
+    /// A required key isn't in the table
+    MissingKey { file: String, table: String, key: String },
+    /// The key isn't one the table can have
+    UnknownKey { file: String, table: String, key: String },
+    /// The value of the key doesn't have the expected type or isn't allowed
+    InvalidValue { file: String, table: String, key: String, expected: &'static str },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io { file, error } => write!(f, "Can't open '{}': {}", file, error),
+            ConfigError::Parse { file, error } => write!(f, "Can't parse '{}': {}", file, error),
+            ConfigError::MissingTable { file, table } => write!(f, "Can't find '{}' in '{}'", table, file),
+            ConfigError::UnknownTable { file, table } => write!(f, "Unknown table '{}' in '{}'", table, file),
+            ConfigError::MissingKey { file, table, key } =>
+                write!(f, "Can't find '{}' in '{}' on '{}'", key, table, file),
+            ConfigError::UnknownKey { file, table, key } =>
+                write!(f, "Unknown key '{}' in '{}' on '{}'", key, table, file),
+            ConfigError::InvalidValue { file, table, key, expected } =>
+                write!(f, "'{}' in '{}' on '{}' should be {}", key, table, file, expected),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Read a toml file
+pub fn read_file(file_name: &str) -> Result<toml::Value, ConfigError> {
+    let content = std::fs::read_to_string(file_name)
+        .map_err(|error| ConfigError::Io { file: file_name.to_string(), error })?;
+    parse(&content, file_name)
+}
+
+/// Parse the toml content of a file
+pub fn parse(content: &str, file_name: &str) -> Result<toml::Value, ConfigError> {
+    toml::from_str(content).map_err(|error| ConfigError::Parse { file: file_name.to_string(), error })
+}
+
+/// A table of a config file, remembers where it comes from for the errors
+pub struct ConfigTable<'c> {
+    pub file: &'c str,
+    pub name: &'c str,
+    pub values: &'c toml::value::Table,
+}
+
+impl<'c> ConfigTable<'c> {
+    pub fn new(config: &'c toml::Value, file: &'c str, name: &'c str) -> Result<Self, ConfigError> {
+        match config.get(name) {
+            Some(toml::Value::Table(values)) => Ok(Self { file, name, values }),
+            Some(_) => Err(ConfigError::InvalidValue { file: file.to_string(), table: name.to_string(),
+                key: name.to_string(), expected: "a table" }),
+            None => Err(ConfigError::MissingTable { file: file.to_string(), table: name.to_string() }),
+        }
+    }
+
+    pub fn missing_key(&self, key: &str) -> ConfigError {
+        ConfigError::MissingKey { file: self.file.to_string(), table: self.name.to_string(), key: key.to_string() }
+    }
+
+    pub fn unknown_key(&self, key: &str) -> ConfigError {
+        ConfigError::UnknownKey { file: self.file.to_string(), table: self.name.to_string(), key: key.to_string() }
+    }
+
+    pub fn invalid_value(&self, key: &str, expected: &'static str) -> ConfigError {
+        ConfigError::InvalidValue { file: self.file.to_string(), table: self.name.to_string(),
+            key: key.to_string(), expected }
+    }
+
+    pub fn optional_integer(&self, key: &str) -> Result<Option<u32>, ConfigError> {
+        match self.values.get(key) {
+            Some(value) => value.as_integer()
+                .and_then(|v| u32::try_from(v).ok())
+                .map(Some)
+                .ok_or(self.invalid_value(key, "a positive integer")),
+            None => Ok(None),
+        }
+    }
+
+    pub fn integer(&self, key: &str) -> Result<u32, ConfigError> {
+        self.optional_integer(key)?.ok_or(self.missing_key(key))
+    }
+
+    pub fn optional_bool(&self, key: &str) -> Result<Option<bool>, ConfigError> {
+        match self.values.get(key) {
+            Some(value) => value.as_bool().map(Some).ok_or(self.invalid_value(key, "a boolean")),
+            None => Ok(None),
+        }
+    }
+
+    pub fn optional_string(&self, key: &str) -> Result<Option<&'c str>, ConfigError> {
+        match self.values.get(key) {
+            Some(value) => value.as_str().map(Some).ok_or(self.invalid_value(key, "a string")),
+            None => Ok(None),
+        }
+    }
+}
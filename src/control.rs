@@ -0,0 +1,186 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::craft::Craft;
+use crate::io::{Objective, SolverResult};
+
+#[cfg(not(feature="no_python"))]
+use pyo3::prelude::*;
+
+/// Nodes a search expands between two checks of the control
+pub(crate) const CHECK_INTERVAL: usize = 1024;
+/// Least time between two progress reports
+const REPORT_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Stops a search from another thread, the clones share the same token
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(not(feature="no_python"), pyclass)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(not(feature="no_python"))]
+#[pymethods]
+impl CancelToken {
+    #[new]
+    fn py_new() -> Self {
+        Self::default()
+    }
+
+    #[pyo3(name = "cancel")]
+    fn py_cancel(&self) {
+        self.cancel()
+    }
+}
+
+/// How far a search went, given to the progress callback
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Progress {
+    /// Routes of the first phase searched by the second one, out of the ones found so far
+    pub phase1_done: usize,
+    pub phase1_routes: usize,
+    /// Crafts expanded by the searches
+    pub nodes: usize,
+    /// Most quality of the finished routes found so far
+    pub best_quality: u32,
+}
+
+/// Called with the progress while the search runs, from any of its threads
+pub type ProgressCallback = Box<dyn Fn(&Progress) + Send + Sync>;
+
+/// Called with every route better than the ones found before it, shared so it runs outside of the lock of the results
+pub type ResultCallback = Arc<dyn Fn(SolverResult) + Send + Sync>;
+
+/// Where the better routes go, along with the key of the best one so far
+#[derive(Default)]
+struct ResultSink {
+    callback: Option<ResultCallback>,
+    best: Option<(i64, i64, i64)>,
+}
+
+struct ControlState {
+    deadline: Option<Instant>,
+    cancel: CancelToken,
+    callback: Option<ProgressCallback>,
+    phase1_done: AtomicUsize,
+    phase1_routes: AtomicUsize,
+    nodes: AtomicUsize,
+    best_quality: AtomicU32,
+    stopped: AtomicBool,
+    last_report: Mutex<Instant>,
+    results: Mutex<ResultSink>,
+}
+
+/// Time limit, cancellation and progress reporting of a search, the clones are shared by its threads
+/// A stopped search returns the best routes it found so far
+#[derive(Clone)]
+pub struct SearchControl(Arc<ControlState>);
+
+impl Default for SearchControl {
+    fn default() -> Self {
+        SearchControl::new(None, CancelToken::default(), None)
+    }
+}
+
+impl SearchControl {
+    /// The time limit starts now
+    pub fn new(time_limit: Option<Duration>, cancel: CancelToken, callback: Option<ProgressCallback>) -> Self {
+        SearchControl(Arc::new(ControlState {
+            deadline: time_limit.map(|limit| Instant::now() + limit),
+            cancel,
+            callback,
+            phase1_done: AtomicUsize::new(0),
+            phase1_routes: AtomicUsize::new(0),
+            nodes: AtomicUsize::new(0),
+            best_quality: AtomicU32::new(0),
+            stopped: AtomicBool::new(false),
+            last_report: Mutex::new(Instant::now()),
+            results: Mutex::new(ResultSink::default()),
+        }))
+    }
+
+    /// Whether the search was cancelled or ran out of time, it then stays stopped
+    pub fn is_stopped(&self) -> bool {
+        if self.0.stopped.load(Ordering::Relaxed) {
+            return true;
+        }
+        let stopped = self.0.cancel.is_cancelled() || self.0.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        if stopped { self.0.stopped.store(true, Ordering::Relaxed); }
+        stopped
+    }
+
+    pub fn progress(&self) -> Progress {
+        Progress {
+            phase1_done: self.0.phase1_done.load(Ordering::Relaxed),
+            phase1_routes: self.0.phase1_routes.load(Ordering::Relaxed),
+            nodes: self.0.nodes.load(Ordering::Relaxed),
+            best_quality: self.0.best_quality.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Count the nodes expanded since the last check, and tell whether the search must stop
+    pub(crate) fn check(&self, nodes: usize) -> bool {
+        self.0.nodes.fetch_add(nodes, Ordering::Relaxed);
+        self.report(false);
+        self.is_stopped()
+    }
+
+    /// Routes of the first phase were found, the second phase searches them while the first one goes on
+    pub(crate) fn add_phase1_routes(&self, routes: usize) {
+        self.0.phase1_routes.fetch_add(routes, Ordering::Relaxed);
+        self.report(false);
+    }
+
+    pub(crate) fn phase1_route_done(&self) {
+        self.0.phase1_done.fetch_add(1, Ordering::Relaxed);
+        self.report(false);
+    }
+
+    /// Give the routes better than the ones found before to the callback, as soon as the searches find them,
+    /// and get back the callback it replaces
+    /// Routes are compared for the objective, or on quality up to the recipe one then steps without one, two routes
+    /// found at the same time by different threads may reach the callback in any order
+    pub fn set_result_callback(&self, callback: Option<ResultCallback>) -> Option<ResultCallback> {
+        let mut results = self.0.results.lock().unwrap();
+        results.best = None;
+        std::mem::replace(&mut results.callback, callback)
+    }
+
+    /// A finished route was found by a search
+    pub(crate) fn found_route(&self, route: &Craft) {
+        self.0.best_quality.fetch_max(route.quality, Ordering::Relaxed);
+        let callback = {
+            let mut results = self.0.results.lock().unwrap();
+            let Some(callback) = results.callback.clone() else { return };
+            let key = route.args.objective.unwrap_or(Objective::LeastSteps).key(&route.state(), route.recipe.quality);
+            if results.best.is_some_and(|best| key <= best) {
+                return;
+            }
+            results.best = Some(key);
+            callback
+        };
+        // The other threads keep finding routes while the callback runs, and it may set another one
+        callback(SolverResult::from_craft(route, 0, 0, route.quality >= route.recipe.quality));
+    }
+
+    /// Give the progress to the callback, at most once per interval unless forced
+    fn report(&self, force: bool) {
+        let Some(callback) = &self.0.callback else { return };
+        {
+            let mut last_report = self.0.last_report.lock().unwrap();
+            if !force && last_report.elapsed() < REPORT_INTERVAL {
+                return;
+            }
+            *last_report = Instant::now();
+        }
+        callback(&self.progress());
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt::{Debug, Formatter,Display};
-use crate::action::Action;
-use crate::specs::{Buff, BuffState, Recipe, Stats, Success};
+use crate::action::{Action, ActionId};
+use crate::action::ActionList;
+use crate::specs::{Buff, BuffState, Condition, Recipe, Stats, Success};
 use crate::Parameters;
 
 #[derive(Clone)]
@@ -14,12 +15,33 @@ pub struct Craft<'a> {
     pub quality: u32,
     pub cp: i32,
     pub success: Success,
+    pub condition: Condition,
     pub actions: Vec<&'a Action>,
     pub args: Parameters,
+    action_table: &'a ActionList,
+}
+
+/// The values of a craft that change with its actions, small enough to be copied around by the solvers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CraftState {
+    pub buffs: BuffState,
+    pub step_count: u32,
+    pub durability: i32,
+    pub progression: u32,
+    pub quality: u32,
+    pub cp: i32,
+    pub success: Success,
 }
 
 impl<'a> Craft<'a> {
+    /// A craft simulated with the actions of the game version, see `with_actions` for the ones of an actions file
     pub fn new(recipe:  Recipe, stats:  Stats, params: Parameters) -> Craft<'a> {
+        let actions = params.game_version.actions();
+        Self::with_actions(recipe, stats, params, actions)
+    }
+
+    /// A craft simulated with the given actions, borrowed for as long as the craft and its routes
+    pub fn with_actions(recipe:  Recipe, stats:  Stats, params: Parameters, actions: &'a ActionList) -> Craft<'a> {
         Self {
             recipe,
             stats,
@@ -30,59 +52,116 @@ impl<'a> Craft<'a> {
             quality: 0,
             cp: stats.max_cp as i32,
             success: Success::Pending,
+            condition: Condition::Normal,
             actions: Vec::new(),
             args: params,
+            action_table: actions,
+        }
+    }
+
+    /// The current values of the craft, without its actions
+    pub fn state(&self) -> CraftState {
+        CraftState {
+            buffs: self.buffs,
+            step_count: self.step_count,
+            durability: self.durability,
+            progression: self.progression,
+            quality: self.quality,
+            cp: self.cp,
+            success: self.success,
         }
     }
 
+    /// Put the craft back to a state, the actions are left untouched
+    pub fn set_state(&mut self, state: CraftState) {
+        self.buffs = state.buffs;
+        self.step_count = state.step_count;
+        self.durability = state.durability;
+        self.progression = state.progression;
+        self.quality = state.quality;
+        self.cp = state.cp;
+        self.success = state.success;
+    }
+
+    /// The actions the craft is simulated with
+    pub fn action_list(&self) -> &'a ActionList {
+        self.action_table
+    }
+
+    /// The progress of a 100 potency action. The formulas are the same in 6.x and 7.x, only the actions change with the
+    /// game version
     pub fn get_base_progression(&self) -> u32 {
         let base_value = (self.stats.craftsmanship as f64 / 10.0) / (self.recipe.progress_divider as f64 / 100.0) + 2.0;
         (base_value * (self.recipe.progress_modifier as f64 / 100.0) as f64).floor() as u32
     }
 
+    /// The quality of a 100 potency action, before the buffs and Inner Quiet
     pub fn get_base_quality(&self) -> u32 {
         let base_value = (self.stats.control as f64 / 10.0) / (self.recipe.quality_divider as f64 / 100.0) + 35.0;
         (base_value * (self.recipe.quality_modifier as f64 / 100.0) as f64).floor() as u32
     }
 
-    pub fn run_action(&mut self, action: &'a Action) -> &mut Craft<'a> {
+    /// Run an action on the craft, the condition is the one shown in game for this step
+    pub fn run_action(&mut self, action: &'a Action, condition: Condition) -> &mut Craft<'a> {
+        self.execute(action, condition, true)
+    }
+
+    /// Run an action that failed its success roll, its costs are paid but it has no effect
+    pub fn run_failed_action(&mut self, action: &'a Action, condition: Condition) -> &mut Craft<'a> {
+        self.execute(action, condition, false)
+    }
+
+    fn execute(&mut self, action: &'a Action, condition: Condition, succeeded: bool) -> &mut Craft<'a> {
+        self.condition = condition;
         if !action.can_use(self) {
             self.success = Success::Failure;
             return self;
         }
+        let basic_touch_combo = self.buffs.basic_touch > 0;
         self.step_count += 1;
         self.cp -= action.get_cp_cost(self) as i32;
         self.durability -= action.get_durability_cost(self) as i32;
-        self.progression += (action.get_progress(self) as f64 * (self.get_base_progression() as f64 / 100.0)).floor() as u32;
-        self.quality += (action.get_quality(self) as f64 * (self.get_base_quality() as f64 / 100.0)).floor() as u32;
+        if action.uses_durability() { self.buffs.remove(Buff::TrainedPerfection); }
+        if succeeded {
+            self.progression += (action.get_progress(self) as f64 * (self.get_base_progression() as f64 / 100.0)).floor() as u32;
+            self.quality += (action.get_quality(self) as f64 * (self.get_base_quality() as f64 / 100.0)).floor() as u32;
+            if action.follows(ActionId::TricksOfTheTrade) { self.cp = (self.cp + 20).min(self.stats.max_cp as i32); }
+            self.durability += action.get_durability_restore(self) as i32;
+            if action.follows(ActionId::TrainedEye) { self.quality = self.quality.max(self.recipe.quality); }
+        }
+        if self.progression >= self.recipe.progress && self.buffs.final_appraisal > 0 {
+            self.progression = self.recipe.progress - 1;
+            self.buffs.remove(Buff::FinalAppraisal);
+        }
         if self.progression >= self.recipe.progress { self.success = Success::Success; }
-        if self.durability <= 0 { self.success = Success::Failure; }
-        if self.buffs.manipulation > 0 { self.durability += 5; }
-        if action.quality > 0 { self.buffs.inner_quiet += 1; }
-        self.buffs.tick();
-        if action.progress > 0 { self.buffs.remove(Buff::MuscleMemory); }
-        if action.quality > 0 { self.buffs.remove(Buff::GreatStrides); }
-        if let Some((buff, duration)) = action.get_buff() { self.buffs.apply(buff, duration); }
+        // The synthesis finishing the craft succeeds even if it breaks the item
+        if self.durability <= 0 && self.success != Success::Success { self.success = Success::Failure; }
+        if action.needs_good_condition() && !matches!(condition, Condition::Good | Condition::Excellent) {
+            self.buffs.remove(Buff::HeartAndSoul);
+        }
+        if action.follows(ActionId::CarefulObservation) { self.buffs.careful_observation += 1; }
+        if action.follows(ActionId::QuickInnovation) { self.buffs.quick_innovation_used = 1; }
+        if action.ticks_buffs() {
+            if self.buffs.manipulation > 0 { self.durability += 5; }
+            self.buffs.tick();
+        }
+        if succeeded {
+            if action.quality > 0 { self.buffs.inner_quiet += 1; }
+            if action.follows(ActionId::RefinedTouch) && basic_touch_combo { self.buffs.inner_quiet += 1; }
+            if action.progress > 0 { self.buffs.remove(Buff::MuscleMemory); }
+            if action.quality > 0 { self.buffs.remove(Buff::GreatStrides); }
+            if let Some((buff, mut duration)) = action.get_buff() {
+                if buff.is_timed() { duration += self.condition.buff_bonus(); }
+                self.buffs.apply(buff, duration);
+            }
+        }
         if self.durability > self.recipe.durability as i32 { self.durability = self.recipe.durability as i32; }
+        if self.success == Success::Success { self.durability = self.durability.max(0); }
         if self.buffs.inner_quiet > 10 { self.buffs.inner_quiet = 10; }
 
         self.actions.push(action);
         return self;
     }
-
-    // Assumes the craft is finished and tries to add the last actions
-    // pub fn add_last_actions(&mut self)->&Self{
-    //     let arg = (self.recipe.progress as f32 - self.progression as f32) / self.get_base_progression() as f32;
-    //     self.durability+=10;
-    //     if 0.0 < arg && arg < 1.2 { self.run_action(&ACTIONS.basic_synthesis);}
-    //     if 1.2 <= arg && arg < 1.8 { self.cp+=7; self.run_action(&ACTIONS.careful_synthesis); }
-    //     if 1.8 <= arg && arg < 2.0 {
-    //         self.cp+=12;
-    //         self.run_action(&ACTIONS.observe);
-    //         self.run_action(&ACTIONS.focused_synthesis);
-    //     }
-    //     self
-    // }
 }
 
 impl<'a> Debug for Craft<'a> {
@@ -111,4 +190,26 @@ impl<'a> Display for Craft<'a> {
             .field("cp", &format!("{:?}/{:?}", &self.cp, &self.stats.max_cp))
             .finish()
     }
-}
\ No newline at end of file
+}
+/// The crafts the tests of the solvers search
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use clap::Parser;
+    use crate::io::Args;
+    use super::*;
+
+    /// The parameters of the command line without arguments
+    pub(crate) fn params() -> Parameters {
+        Parameters::from_args(&Args::parse_from(["ffcraft"]))
+    }
+
+    /// A level 100 crafter
+    pub(crate) fn stats(max_cp: u32) -> Stats {
+        Stats { craftsmanship: 4000, control: 3900, max_cp, specialist: false, level: 100 }
+    }
+
+    /// A level 90 recipe of 6.x made by the crafter of `stats`
+    pub(crate) fn craft<'a>(durability: u32, progress: u32, quality: u32, max_cp: u32, params: Parameters) -> Craft<'a> {
+        Craft::new(Recipe::from_rlvl(640, progress, quality, durability).unwrap(), stats(max_cp), params)
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use crate::action::{Action, ActionId};
+use crate::bound::QualityBound;
+use crate::control::{SearchControl, CHECK_INTERVAL};
+use crate::craft::{Craft, CraftState};
+use crate::io::Opener;
+use crate::solver::Finisher;
+use crate::specs::{BuffState, Condition, Success};
+
+/// The states already reached for each progression and buffs, as (quality, cp, durability, steps)
+/// Only the ones not beaten on all four values by another are kept
+type Table = HashMap<(u32, BuffState), Vec<(u32, i32, i32, u32)>>;
+
+/// Depth first search over the states the craft can reach in Normal condition, with the actions that can't fail
+/// The best route is the one reaching the most quality, up to the recipe one, in the least steps
+pub struct ExactSolver<'a> {
+    /// Craft used to simulate the actions, its actions are cleared before each one
+    scratch: Craft<'a>,
+    actions: Vec<&'a Action>,
+    table: Table,
+    /// The actions leading to the state being searched
+    path: Vec<ActionId>,
+    best: Option<(CraftState, Vec<ActionId>)>,
+    bound: QualityBound,
+    /// Finishes the craft being searched when stopped before any route
+    finisher: Finisher<'a>,
+    control: SearchControl,
+    stopped: bool,
+    /// The opener asked for, the classic one is left to the phases
+    opener: Opener,
+    pub nb_states: usize,
+}
+
+impl<'a> ExactSolver<'a> {
+    pub fn new(craft: &Craft<'a>, control: &SearchControl) -> Self {
+        // Careful Observation is left out since the condition is always Normal
+        let actions = craft.action_list().iter()
+            .filter(|a| a.id != ActionId::CarefulObservation)
+            .filter(|a| craft.args.game_version.has_action(a.id) && craft.stats.level >= a.level)
+            .collect();
+        let mut scratch = craft.clone();
+        scratch.actions.clear();
+        Self {
+            scratch,
+            actions,
+            table: Table::new(),
+            path: Vec::new(),
+            best: None,
+            bound: QualityBound::new(craft),
+            finisher: Finisher::new(craft),
+            control: control.clone(),
+            stopped: false,
+            opener: match &craft.args.opener {
+                Opener::Classic => Opener::Free,
+                opener => opener.clone(),
+            },
+            nb_states: 0,
+        }
+    }
+
+    /// Search from the craft and return the best route found, the best one so far when stopped,
+    /// or the craft being searched when stopped finished by the last synthesis when none was found yet
+    pub fn solve(&mut self, craft: Craft<'a>) -> Option<Craft<'a>> {
+        self.search(craft.state());
+        let (_, ids) = self.best.as_ref()?;
+        let mut route = craft;
+        let actions = route.action_list();
+        for &id in ids {
+            route.run_action(actions.get(id), Condition::Normal);
+        }
+        Some(route)
+    }
+
+    fn search(&mut self, state: CraftState) {
+        self.nb_states += 1;
+        if self.nb_states.is_multiple_of(CHECK_INTERVAL) && self.control.check(CHECK_INTERVAL) {
+            self.stopped = true;
+        }
+        if self.stopped {
+            self.finish_stopped(state);
+            return;
+        }
+        for i in 0..self.actions.len() {
+            let action = self.actions[i];
+            if self.opener.allowed_actions(state.step_count).is_some_and(|allowed| !allowed.contains(&action.id)) {
+                continue;
+            }
+            self.scratch.set_state(state);
+            // Actions that can fail would make the route a gamble
+            if !action.can_use(&self.scratch) || action.get_success_rate(&self.scratch) < 100 {
+                continue;
+            }
+            self.scratch.actions.clear();
+            self.scratch.run_action(action, Condition::Normal);
+            let child = self.scratch.state();
+            self.path.push(action.id);
+            match child.success {
+                Success::Failure => {},
+                Success::Success => {
+                    if self.best.as_ref().is_none_or(|(best, _)| self.is_better(&child, best)) {
+                        self.found_route(child, &self.path);
+                        self.best = Some((child, self.path.clone()));
+                    }
+                },
+                Success::Pending => {
+                    if self.can_beat_best(&child) && !self.is_dominated(&child) {
+                        self.search(child);
+                    }
+                },
+            }
+            self.path.pop();
+        }
+        if self.stopped {
+            self.finish_stopped(state);
+        }
+    }
+
+    /// Keep the state finished by the last synthesis when the search stopped before finding any route,
+    /// the search unwinding from the deepest state the first one that can be finished is kept
+    fn finish_stopped(&mut self, state: CraftState) {
+        if self.best.is_some() {
+            return;
+        }
+        if let Some((finished, ids)) = self.finisher.finish(state) {
+            let (finished, mut path) = (*finished, self.path.clone());
+            path.extend(ids);
+            self.found_route(finished, &path);
+            self.best = Some((finished, path));
+        }
+    }
+
+    /// Give a better route to the control, as a craft made of the state and the actions leading to it
+    fn found_route(&self, state: CraftState, path: &[ActionId]) {
+        let mut route = self.scratch.clone();
+        route.set_state(state);
+        let actions = route.action_list();
+        route.actions = path.iter().map(|&id| actions.get(id)).collect();
+        self.control.found_route(&route);
+    }
+
+    /// Whether a finished state is better than another for the objective, or without one on quality up to the recipe one,
+    /// then steps, then remaining quality, durability and cp
+    fn is_better(&self, state: &CraftState, other: &CraftState) -> bool {
+        let target = self.scratch.recipe.quality;
+        if let Some(objective) = self.scratch.args.objective {
+            return objective.key(state, target) > objective.key(other, target);
+        }
+        let key = |s: &CraftState| (s.quality.min(target), std::cmp::Reverse(s.step_count), s.quality, s.durability, s.cp);
+        key(state) > key(other)
+    }
+
+    /// Whether the quality bound of a state still leaves it a chance to beat the best route,
+    /// finishing from it takes at least one more step, the scratch craft is in that state
+    fn can_beat_best(&self, state: &CraftState) -> bool {
+        let (best, _) = match &self.best {
+            Some(best) => best,
+            None => return true,
+        };
+        let target = self.scratch.recipe.quality;
+        let missing_progress = self.scratch.recipe.progress - state.progression;
+        let max_quality = self.bound.max_quality(state, missing_progress);
+        if let Some(objective) = self.scratch.args.objective {
+            return objective.max_steps().is_none_or(|max_steps| state.step_count < max_steps)
+                && objective.can_beat(max_quality, &self.scratch, best);
+        }
+        (max_quality.min(target), std::cmp::Reverse(state.step_count + 1)) > (best.quality.min(target), std::cmp::Reverse(best.step_count))
+    }
+
+    /// Whether a state with the same progression and buffs and at least as much quality, cp and durability
+    /// was reached in as many steps or less, the state is added to the table otherwise
+    fn is_dominated(&mut self, state: &CraftState) -> bool {
+        let quality = match self.scratch.args.objective {
+            Some(objective) if !objective.caps_quality() => state.quality,
+            _ => state.quality.min(self.scratch.recipe.quality),
+        };
+        let reached = self.table.entry((state.progression, state.buffs)).or_default();
+        is_dominated(reached, (quality, state.cp, state.durability, state.step_count))
+    }
+}
+
+/// Whether some values reached, as (quality, cp, durability, steps), are beaten on all four by others already reached,
+/// they are added to the reached ones otherwise
+pub(crate) fn is_dominated(reached: &mut Vec<(u32, i32, i32, u32)>, values: (u32, i32, i32, u32)) -> bool {
+    let dominates = |a: &(u32, i32, i32, u32), b: &(u32, i32, i32, u32)|
+        a.0 >= b.0 && a.1 >= b.1 && a.2 >= b.2 && a.3 <= b.3;
+    if reached.iter().any(|r| dominates(r, &values)) {
+        return true;
+    }
+    reached.retain(|r| !dominates(&values, r));
+    reached.push(values);
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::craft::fixtures::{craft, params};
+    use crate::io::{Objective, Parameters, ScoreWeights};
+    use super::*;
+
+    fn small_craft<'a>() -> Craft<'a> {
+        craft(20, 600, 2000, 40, Parameters { opener: Opener::Free, ..params() })
+    }
+
+    /// The best finished state reachable from a state for the key, trying every usable action that can't fail
+    fn brute_force<K: Ord>(craft: &mut Craft, state: CraftState, key: &impl Fn(&CraftState) -> K,
+                           seen: &mut HashMap<CraftState, Option<CraftState>>) -> Option<CraftState> {
+        if let Some(&best) = seen.get(&state) {
+            return best;
+        }
+        let mut best: Option<CraftState> = None;
+        for action in craft.action_list().iter().filter(|a| a.id != ActionId::CarefulObservation) {
+            craft.set_state(state);
+            if !action.can_use(craft) || action.get_success_rate(craft) < 100 {
+                continue;
+            }
+            craft.actions.clear();
+            craft.run_action(action, Condition::Normal);
+            let child = craft.state();
+            let finished = match child.success {
+                Success::Success => Some(child),
+                Success::Failure => None,
+                Success::Pending => brute_force(craft, child, key, seen),
+            };
+            if let Some(finished) = finished {
+                if best.is_none_or(|b| key(&finished) > key(&b)) {
+                    best = Some(finished);
+                }
+            }
+        }
+        seen.insert(state, best);
+        best
+    }
+
+    #[test]
+    fn finds_the_best_route() {
+        let craft = small_craft();
+        let route = ExactSolver::new(&craft, &SearchControl::default()).solve(craft.clone()).unwrap();
+        let target = craft.recipe.quality;
+        let key = |s: &CraftState| (s.quality.min(target), std::cmp::Reverse(s.step_count), s.quality, s.durability, s.cp);
+        let best = brute_force(&mut craft.clone(), craft.state(), &key, &mut HashMap::new()).unwrap();
+        assert_eq!((route.quality.min(craft.recipe.quality), route.step_count), (best.quality.min(craft.recipe.quality), best.step_count));
+        assert_eq!(route.success, Success::Success);
+        assert_eq!(route.actions.len() as u32, route.step_count);
+    }
+
+    #[test]
+    fn finds_the_best_weighted_route() {
+        let mut craft = small_craft();
+        let weights = ScoreWeights { quality: 1.0, steps: -50.0, cp: 2.0, durability: 10.0 };
+        craft.args.objective = Some(Objective::Weighted(weights));
+        let route = ExactSolver::new(&craft, &SearchControl::default()).solve(craft.clone()).unwrap();
+        let best = brute_force(&mut craft.clone(), craft.state(), &|s: &CraftState| weights.score(s), &mut HashMap::new()).unwrap();
+        assert_eq!(weights.score(&route.state()), weights.score(&best));
+        // More steps raising the score leave it unbounded
+        assert!(ScoreWeights { steps: 1.0, ..weights }.max_score(route.quality, &craft).is_none());
+    }
+
+    #[test]
+    fn keeps_the_values_not_beaten() {
+        let mut reached = Vec::new();
+        assert!(!is_dominated(&mut reached, (100, 50, 20, 5)));
+        // Beaten or equalled on all four values
+        assert!(is_dominated(&mut reached, (100, 50, 20, 5)));
+        assert!(is_dominated(&mut reached, (90, 40, 10, 6)));
+        // Better on one value only
+        assert!(!is_dominated(&mut reached, (110, 40, 10, 6)));
+        assert!(!is_dominated(&mut reached, (90, 40, 10, 4)));
+        assert_eq!(reached.len(), 3);
+        // Beating all the others replaces them
+        assert!(!is_dominated(&mut reached, (120, 60, 30, 3)));
+        assert_eq!(reached, [(120, 60, 30, 3)]);
+    }
+}
@@ -0,0 +1,262 @@
+use crate::action::{Action, ActionId};
+use crate::control::{SearchControl, CHECK_INTERVAL};
+use crate::craft::Craft;
+use crate::io::{Objective, Opener};
+use crate::solver::Finisher;
+use crate::specs::{Condition, Success};
+
+/// Most actions of a rotation, the finisher comes on top of them
+const MAX_GENES: usize = 40;
+/// Actions of the rotations of the first generation
+const FIRST_GENES: std::ops::Range<usize> = 10..30;
+/// Best rotations copied as they are to the next generation
+const ELITES: usize = 4;
+/// Rotations drawn to pick a parent, the best one wins
+const TOURNAMENT: usize = 3;
+/// Best distinct rotations returned
+const KEPT_ROUTES: usize = 10;
+
+/// How good a rotation is, the higher the better
+/// Finished rotations come first and are compared for the objective, the others on their progression then quality
+type Fitness = (bool, (i64, i64, i64));
+
+/// Small random generator (SplitMix64), the same seed always gives the same numbers on every platform
+pub(crate) struct Rng(pub u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number below the bound, which must not be 0
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+
+    /// A number between 0 included and 1 excluded
+    pub fn fraction(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Genetic algorithm evolving rotations from a seed, each one being run on the craft to be evaluated
+///
+/// The actions that can't be used when their turn comes, that can fail or that would break the item are skipped,
+/// and the rotations that don't finish the craft get the finisher of the other searches, so every rotation
+/// gives a route. The condition is always Normal. It stops after its generations or when the control stops it,
+/// so it is reproducible as long as it isn't stopped by the time limit.
+pub struct GeneticSolver<'a> {
+    craft: Craft<'a>,
+    actions: Vec<&'a Action>,
+    objective: Objective,
+    /// The opener asked for, the classic one is left to the phases
+    opener: Opener,
+    control: SearchControl,
+    finisher: Finisher<'a>,
+    rng: Rng,
+    /// Best distinct routes found, the best first
+    best: Vec<(Fitness, Craft<'a>)>,
+    pub nb_rotations: usize,
+}
+
+impl<'a> GeneticSolver<'a> {
+    pub fn new(craft: &Craft<'a>, control: &SearchControl) -> Self {
+        // The actions depending on the condition are left out since it is always Normal
+        let actions = craft.action_list().iter()
+            .filter(|a| !matches!(a.id, ActionId::CarefulObservation | ActionId::HeartAndSoul) && !a.needs_good_condition())
+            .filter(|a| craft.args.game_version.has_action(a.id) && craft.stats.level >= a.level)
+            .collect();
+        let mut craft = craft.clone();
+        craft.actions.clear();
+        // A fixed opener is run once here, so the rotations only evolve what comes after it
+        let opener = match craft.args.opener.clone() {
+            Opener::Classic => Opener::Free,
+            Opener::Fixed(ids) => {
+                for &id in ids.iter() {
+                    let action = craft.action_list().get(id);
+                    if craft.success == Success::Pending && action.can_use(&craft) {
+                        craft.run_action(action, Condition::Normal);
+                    }
+                }
+                Opener::Free
+            }
+            opener => opener,
+        };
+        Self {
+            objective: craft.args.objective.unwrap_or(Objective::LeastSteps),
+            opener,
+            rng: Rng(craft.args.seed),
+            finisher: Finisher::new(&craft),
+            craft,
+            actions,
+            control: control.clone(),
+            best: Vec::new(),
+            nb_rotations: 0,
+        }
+    }
+
+    /// Evolve the rotations and return the best routes found, the best ones so far when stopped
+    pub fn solve(&mut self) -> Vec<Craft<'a>> {
+        let size = self.craft.args.population.max(ELITES + 1);
+        let mut population = (0..size).map(|_| {
+            let length = FIRST_GENES.start + self.rng.below(FIRST_GENES.len());
+            (0..length).map(|_| self.random_action()).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+
+        'evolve: for _ in 0..self.craft.args.generations {
+            let mut scored = Vec::with_capacity(population.len());
+            for genes in population {
+                self.nb_rotations += 1;
+                if self.nb_rotations.is_multiple_of(CHECK_INTERVAL) && self.control.check(CHECK_INTERVAL) {
+                    break 'evolve;
+                }
+                let fitness = self.evaluate(&genes);
+                scored.push((fitness, genes));
+            }
+            // Stable, so the rotations with the same fitness keep their order
+            scored.sort_by_key(|(fitness, _)| std::cmp::Reverse(*fitness));
+
+            population = scored.iter().take(ELITES).map(|(_, genes)| genes.clone()).collect();
+            while population.len() < size {
+                let first = self.tournament(&scored);
+                let second = self.tournament(&scored);
+                let mut child = self.crossover(first, second);
+                self.mutate(&mut child);
+                population.push(child);
+            }
+        }
+        self.best.drain(..).map(|(_, route)| route).collect()
+    }
+
+    /// Run the rotation on the craft, keep its route if it is among the best ones and give its fitness
+    fn evaluate(&mut self, genes: &[ActionId]) -> Fitness {
+        let mut craft = self.craft.clone();
+        let actions = craft.action_list();
+        for &id in genes {
+            if craft.success != Success::Pending {
+                break;
+            }
+            let action = actions.get(id);
+            if self.opener.allowed_actions(craft.step_count).is_some_and(|allowed| !allowed.contains(&id))
+                || !action.can_use(&craft) || action.get_success_rate(&craft) < 100 {
+                continue;
+            }
+            let before = craft.state();
+            craft.run_action(action, Condition::Normal);
+            if craft.success == Success::Failure {
+                craft.set_state(before);
+                craft.actions.pop();
+            }
+        }
+        let target = craft.recipe.quality;
+        let route = match craft.success {
+            Success::Success => craft,
+            // The opener must be played before the finisher takes over
+            _ => match self.opener.allowed_actions(craft.step_count).is_none().then(|| self.finisher.finish_craft(&craft)).flatten() {
+                Some(route) => route,
+                None => return (false, (craft.progression as i64, craft.quality as i64, -(craft.step_count as i64))),
+            },
+        };
+        let fitness = (true, self.objective.key(&route.state(), target));
+        self.keep(fitness, route);
+        fitness
+    }
+
+    /// Keep a finished route if it is among the best distinct ones
+    fn keep(&mut self, fitness: Fitness, route: Craft<'a>) {
+        if self.best.len() >= KEPT_ROUTES && self.best.last().is_some_and(|(worst, _)| fitness <= *worst) {
+            return;
+        }
+        if self.best.iter().any(|(_, kept)| kept.actions == route.actions) {
+            return;
+        }
+        if self.best.first().is_none_or(|(best, _)| fitness > *best) {
+            self.control.found_route(&route);
+        }
+        let index = self.best.partition_point(|(kept, _)| *kept >= fitness);
+        self.best.insert(index, (fitness, route));
+        self.best.truncate(KEPT_ROUTES);
+    }
+
+    fn random_action(&mut self) -> ActionId {
+        self.actions[self.rng.below(self.actions.len())].id
+    }
+
+    /// The best of some rotations drawn from the sorted ones
+    fn tournament<'p>(&mut self, scored: &'p [(Fitness, Vec<ActionId>)]) -> &'p [ActionId] {
+        let index = (0..TOURNAMENT).map(|_| self.rng.below(scored.len())).min().unwrap();
+        &scored[index].1
+    }
+
+    /// The start of a rotation followed by the end of another
+    fn crossover(&mut self, first: &[ActionId], second: &[ActionId]) -> Vec<ActionId> {
+        let cut_first = self.rng.below(first.len() + 1);
+        let cut_second = self.rng.below(second.len() + 1);
+        let mut child = first[..cut_first].to_vec();
+        child.extend_from_slice(&second[cut_second..]);
+        child.truncate(MAX_GENES);
+        child
+    }
+
+    /// Change, add, remove or swap some actions
+    fn mutate(&mut self, genes: &mut Vec<ActionId>) {
+        if self.rng.chance(30) && !genes.is_empty() {
+            let index = self.rng.below(genes.len());
+            genes[index] = self.random_action();
+        }
+        if self.rng.chance(20) && genes.len() < MAX_GENES {
+            let index = self.rng.below(genes.len() + 1);
+            let action = self.random_action();
+            genes.insert(index, action);
+        }
+        if self.rng.chance(20) && !genes.is_empty() {
+            let index = self.rng.below(genes.len());
+            genes.remove(index);
+        }
+        if self.rng.chance(20) && genes.len() >= 2 {
+            let (first, second) = (self.rng.below(genes.len()), self.rng.below(genes.len()));
+            genes.swap(first, second);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::craft::fixtures::{craft, params};
+    use crate::io::Parameters;
+    use super::*;
+
+    fn routes(seed: u64) -> Vec<(Vec<ActionId>, u32)> {
+        let craft = craft(70, 3900, 10920, 602, Parameters { seed, generations: 20, population: 30, ..params() });
+        GeneticSolver::new(&craft, &SearchControl::default()).solve().iter()
+            .map(|route| (route.actions.iter().map(|a| a.id).collect(), route.quality))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_routes() {
+        let first = routes(7);
+        assert!(!first.is_empty());
+        assert_eq!(first, routes(7));
+        assert_ne!(first, routes(8));
+    }
+
+    #[test]
+    fn rng_is_reproducible() {
+        let (mut first, mut second) = (Rng(3), Rng(3));
+        for _ in 0..100 {
+            assert_eq!(first.next(), second.next());
+            let fraction = first.fraction();
+            assert!((0.0..1.0).contains(&fraction));
+            second.fraction();
+        }
+    }
+}
@@ -1,17 +1,282 @@
 use crate::Craft;
+use crate::craft::CraftState;
+use crate::specs::{GameVersion, MAX_LEVEL};
+use crate::action::{ActionId, ActionList};
 use core::fmt::Display;
-use clap::Parser;
+use std::sync::Arc;
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[cfg(not(feature="no_python"))]
 use pyo3::prelude::*;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct Parameters {
     pub threads: usize,
     pub verbose: u8,
     pub depth: u32,
     pub desperate:bool,
     pub byregot_step: u8,
+    pub game_version: GameVersion,
+    pub action_table: Option<Arc<ActionList>>,
+    pub mode: SearchMode,
+    pub opener: Opener,
+    pub objective: Option<Objective>,
+    pub beam_width: usize,
+    pub beam_heuristic: BeamHeuristic,
+    pub seed: u64,
+    pub generations: u32,
+    pub population: usize,
+}
+
+/// How the solver searches for the routes
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum SearchMode {
+    /// Reach the progression first then add the touches, quick but may miss some routes
+    Phases,
+    /// Search every state the craft can reach, slower but finds the best route
+    Exact,
+    /// Search the progression and the touches together with every action, the synthesis can come between the touches
+    Unified,
+    /// Keep the best crafts of each step for a heuristic, quick but approximate
+    Beam,
+    /// Evolve action sequences from a seed, for long rotations the other modes can't search
+    Genetic,
+}
+
+impl std::str::FromStr for SearchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+/// The usual opener of the phases, kept as the default
+const CLASSIC_OPENER: [ActionId; 3] = [ActionId::MuscleMemory, ActionId::Manipulation, ActionId::Veneration];
+
+/// How the first steps of the craft are chosen
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Opener {
+    /// Muscle Memory, Manipulation then Veneration, the exact search ignores it and starts freely
+    #[default]
+    Classic,
+    /// These actions first, in this order, the search goes on from them
+    Fixed(Arc<[ActionId]>),
+    /// One of these actions first, the rest of the opener is searched
+    FirstOf(Arc<[ActionId]>),
+    /// The opener is searched, with any of the first step actions and buffs
+    Free,
+}
+
+impl Opener {
+    /// The actions allowed at a step of the craft, None when the search picks them
+    pub fn allowed_actions(&self, step: u32) -> Option<&[ActionId]> {
+        let step = step as usize;
+        match self {
+            Opener::Classic => CLASSIC_OPENER.get(step..=step),
+            Opener::Fixed(ids) => ids.get(step..=step),
+            Opener::FirstOf(ids) if step == 0 => Some(ids),
+            _ => None,
+        }
+    }
+}
+
+/// `classic`, `free`, a list of actions like `reflect,manipulation` for a fixed prefix,
+/// or `first:muscle_memory,reflect` for the allowed first actions
+impl std::str::FromStr for Opener {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_ids = |list: &str| -> Result<Arc<[ActionId]>, String> {
+            list.split(',')
+                .map(|name| name.trim().parse::<ActionId>().map_err(|_| format!("Unknown action '{}' in the opener", name.trim())))
+                .collect()
+        };
+        match s.trim() {
+            "classic" => Ok(Opener::Classic),
+            "free" => Ok(Opener::Free),
+            list => match list.strip_prefix("first:") {
+                Some(list) => Ok(Opener::FirstOf(parse_ids(list)?)),
+                None => Ok(Opener::Fixed(parse_ids(list)?)),
+            },
+        }
+    }
+}
+
+/// What the search optimises, without one the routes reaching the most quality are all kept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Objective {
+    /// Least steps reaching the recipe quality
+    LeastSteps,
+    /// Least cp used reaching the recipe quality
+    LeastCp,
+    /// Most quality within some steps
+    MostQuality(u32),
+    /// Most durability left reaching the recipe quality
+    MostDurability,
+    /// Highest sum of the quality, steps, cp left and durability left times their weights
+    Weighted(ScoreWeights),
+}
+
+/// The weights of the score of a route, negative ones make the value a cost
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreWeights {
+    pub quality: f64,
+    pub steps: f64,
+    pub cp: f64,
+    pub durability: f64,
+}
+
+impl Objective {
+    /// Key of a finished route, the higher the better
+    pub fn key(&self, state: &CraftState, target: u32) -> (i64, i64, i64) {
+        let quality = state.quality as i64;
+        let capped = quality.min(target as i64);
+        let steps = state.step_count as i64;
+        match *self {
+            Objective::LeastSteps => (capped, -steps, quality),
+            Objective::LeastCp => (capped, state.cp as i64, -steps),
+            Objective::MostQuality(max_steps) => ((state.step_count <= max_steps) as i64, quality, -steps),
+            Objective::MostDurability => (capped, state.durability as i64, quality),
+            Objective::Weighted(w) => (w.score(state), capped, -steps),
+        }
+    }
+
+    /// Whether a route from the craft whose quality stays under the bound can still beat the best one
+    pub fn can_beat(&self, max_quality: u32, craft: &Craft, best: &CraftState) -> bool {
+        let target = craft.recipe.quality;
+        match self {
+            Objective::Weighted(w) => w.max_score(max_quality, craft).is_none_or(|max_score| max_score >= w.score(best)),
+            Objective::MostQuality(_) => max_quality >= best.quality,
+            _ => max_quality.min(target) >= best.quality.min(target),
+        }
+    }
+
+    /// Whether the quality over the recipe one only matters to break ties
+    pub fn caps_quality(&self) -> bool {
+        !matches!(self, Objective::MostQuality(_) | Objective::Weighted(_))
+    }
+
+    /// Most steps a route can take
+    pub fn max_steps(&self) -> Option<u32> {
+        match *self {
+            Objective::MostQuality(max_steps) => Some(max_steps),
+            _ => None,
+        }
+    }
+}
+
+/// `least_steps`, `least_cp`, `most_quality:<steps>`, `most_durability`
+/// or `weighted:<quality>,<steps>,<cp>,<durability>` for the weights of the score
+impl std::str::FromStr for Objective {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, values) = s.trim().split_once(':').unwrap_or((s.trim(), ""));
+        match name {
+            "least_steps" => Ok(Objective::LeastSteps),
+            "least_cp" => Ok(Objective::LeastCp),
+            "most_durability" => Ok(Objective::MostDurability),
+            "most_quality" => values.trim().parse()
+                .map(Objective::MostQuality)
+                .map_err(|_| format!("The most quality objective needs its steps, like 'most_quality:20', not '{}'", s)),
+            "weighted" => values.parse()
+                .map(Objective::Weighted)
+                .map_err(|_| format!("The weighted objective needs the weights of the quality, steps, cp and durability, like 'weighted:1,-100,1,5', not '{}'", s)),
+            _ => Err(format!("Unknown objective '{}'", s)),
+        }
+    }
+}
+
+impl ScoreWeights {
+    /// The score of a craft, scaled to keep two decimals
+    pub fn score(&self, state: &CraftState) -> i64 {
+        let score = self.quality * state.quality as f64 + self.steps * state.step_count as f64
+            + self.cp * state.cp as f64 + self.durability * state.durability as f64;
+        (score * 100.0).round() as i64
+    }
+
+    /// The highest score a route from the craft can end with, each value taken at the best end it can reach:
+    /// the quality up to its bound, one more step at least, the cp left with one Tricks of the Trade at most
+    /// and the durability left up to the recipe one, none when more steps raise the score
+    pub fn max_score(&self, max_quality: u32, craft: &Craft) -> Option<i64> {
+        if self.steps > 0.0 {
+            return None;
+        }
+        let quality = if self.quality > 0.0 { max_quality } else { craft.quality };
+        let cp = if self.cp > 0.0 { (craft.cp + 20).min(craft.stats.max_cp as i32) } else { 0 };
+        let durability = if self.durability > 0.0 { craft.recipe.durability as i32 } else { 0 };
+        let score = self.quality * quality as f64 + self.steps * (craft.step_count + 1) as f64
+            + self.cp * cp as f64 + self.durability * durability as f64;
+        Some((score * 100.0).round() as i64)
+    }
+}
+
+/// `<quality>,<steps>,<cp>,<durability>`
+impl std::str::FromStr for ScoreWeights {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let weights = s.split(',').map(|v| v.trim().parse::<f64>()).collect::<Result<Vec<_>, _>>();
+        match weights.as_deref() {
+            Ok(&[quality, steps, cp, durability]) => Ok(ScoreWeights { quality, steps, cp, durability }),
+            _ => Err(format!("Expected the weights of the quality, steps, cp and durability, not '{}'", s)),
+        }
+    }
+}
+
+impl Display for Objective {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Objective::LeastSteps => write!(f, "Least steps"),
+            Objective::LeastCp => write!(f, "Least cp"),
+            Objective::MostQuality(max_steps) => write!(f, "Most quality in {} steps", max_steps),
+            Objective::MostDurability => write!(f, "Most durability"),
+            Objective::Weighted(_) => write!(f, "Best score"),
+        }
+    }
+}
+
+/// How the beam search ranks the crafts of a step, the best ones are kept
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BeamHeuristic {
+    /// Most quality the craft can still reach up to the recipe one, then the most progression,
+    /// then the most durability and cp left, a point of durability being worth 20 cp
+    #[default]
+    Bound,
+    /// Most progression then quality, then durability and cp left, like the phases
+    Progress,
+    /// Like the bound, with the highest sum of the quality, steps, cp left and durability left times their weights
+    /// instead of the durability and cp left
+    Weighted(ScoreWeights),
+}
+
+impl BeamHeuristic {
+    /// Score of an unfinished craft, the higher the better, the quality bound is the most quality it can still reach
+    pub fn score(&self, state: &CraftState, max_quality: u32, target: u32) -> (i64, i64, i64) {
+        match *self {
+            BeamHeuristic::Bound => (max_quality.min(target) as i64, state.progression as i64, state.durability as i64 * 20 + state.cp as i64),
+            BeamHeuristic::Progress => (state.progression as i64, state.quality as i64, state.durability as i64 * 20 + state.cp as i64),
+            BeamHeuristic::Weighted(w) => (max_quality.min(target) as i64, state.progression as i64, w.score(state)),
+        }
+    }
+}
+
+/// `bound`, `progress` or `weighted:<quality>,<steps>,<cp>,<durability>` for the weights of the score
+impl std::str::FromStr for BeamHeuristic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, values) = s.trim().split_once(':').unwrap_or((s.trim(), ""));
+        match name {
+            "bound" => Ok(BeamHeuristic::Bound),
+            "progress" => Ok(BeamHeuristic::Progress),
+            "weighted" => values.parse()
+                .map(BeamHeuristic::Weighted)
+                .map_err(|_| format!("The weighted heuristic needs the weights of the quality, steps, cp and durability, like 'weighted:1,0,2,5', not '{}'", s)),
+            _ => Err(format!("Unknown beam heuristic '{}'", s)),
+        }
+    }
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -37,8 +302,8 @@ pub struct Args {
     #[arg(short, long, default_value_t = 8)]
     pub depth: u32,
 
-    /// Thread counts, default is 4
-    #[arg(short, long, default_value_t = 4)]
+    /// Thread count, default is the number of cores
+    #[arg(short, long, default_value_t = 0, hide_default_value = true)]
     pub threads: usize,
 
     /// Desperate mode, will try to finish the craft above all
@@ -48,12 +313,94 @@ pub struct Args {
     /// Long mode, will try to find more solutions, at the expense of time
     #[arg(short='l', long, default_value_t = false)]
     pub long: bool,
+
+    /// Game patch whose actions are used
+    #[arg(short, long, value_enum, default_value_t = GameVersion::Dawntrail)]
+    pub game_version: GameVersion,
+
+    /// Toml file patching the actions of the game version
+    #[arg(short, long)]
+    pub actions_file: Option<String>,
+
+    /// Search mode, the exact, unified, beam and genetic ones only use actions that can't fail
+    #[arg(short, long, value_enum, default_value_t = SearchMode::Phases)]
+    pub mode: SearchMode,
+
+    /// Opener of the craft: classic, free, a fixed list of actions (muscle_memory,manipulation) or the allowed first ones (first:reflect,muscle_memory)
+    #[arg(short, long, default_value = "classic")]
+    pub opener: Opener,
+
+    /// What the search optimises: least_steps, least_cp, most_quality:<steps>, most_durability or weighted:<quality>,<steps>,<cp>,<durability>
+    #[arg(short='O', long)]
+    pub objective: Option<Objective>,
+
+    /// Crafts kept at each step by the beam mode
+    #[arg(short='w', long, default_value_t = 1000)]
+    pub beam_width: usize,
+
+    /// How the beam mode ranks the crafts: bound, progress or weighted:<quality>,<steps>,<cp>,<durability>
+    #[arg(long, default_value = "bound")]
+    pub beam_heuristic: BeamHeuristic,
+
+    /// Seed of the genetic mode and of the policy, the same seed gives the same results
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Generations evolved by the genetic mode
+    #[arg(long, default_value_t = 300)]
+    pub generations: u32,
+
+    /// Rotations of each generation of the genetic mode
+    #[arg(long, default_value_t = 200)]
+    pub population: usize,
+
+    /// Show every route no other one beats on quality, steps, durability and cp instead of the best ones
+    #[arg(short='P', long, default_value_t = false)]
+    pub pareto: bool,
+
+    /// Stop the search after these seconds and show the best routes found so far
+    #[arg(short='T', long)]
+    pub time_limit: Option<f64>,
+
+    /// Show the better routes as soon as the search finds them
+    #[arg(short, long, default_value_t = false)]
+    pub stream: bool,
+
+    /// Directory of the Recipe, RecipeLevelTable and Item csv exports, the recipe name is then searched in it
+    #[arg(short='R', long)]
+    pub recipe_db: Option<String>,
+
+    /// Find the action giving the most expected quality for each state and condition instead of a route, and write this decision table to a csv file
+    #[arg(long)]
+    pub policy: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Browse the recipe database given with --recipe-db
+    Recipes {
+        #[command(subcommand)]
+        command: RecipesCommand,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum RecipesCommand {
+    /// Search the recipes whose name looks like the text
+    Search {
+        text: String,
+    },
+    /// Print the recipe levels in the format of data/recipe_level_table.csv
+    Levels,
 }
 
 
 /// A final stripped down version of a craft
 /// used for final print and talking with python
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[pyclass]
 pub struct SolverResult{
     #[pyo3(get)]
@@ -95,18 +442,9 @@ impl Display for SolverResult{
 //#[pymethods]
 impl SolverResult{
     pub fn from_craft(craft: & Craft,step1_solutions : usize,step2_solutions : usize, found_100_percent: bool)->SolverResult{
-        // Todo: recreate actions
-        // Where steps ?
-        let mut actions = craft.actions.iter().map(|action| 
+        let actions = craft.actions.iter().map(|action| 
             format!("{}",action.short_name)
         ).collect::<Vec<String>>();
-        let arg = (craft.recipe.progress as f32 - craft.progression as f32) / craft.get_base_progression() as f32;
-        if 0.0 < arg && arg < 1.2 { actions.push("basicSynth2".to_string()); }
-        if 1.2 <= arg && arg < 1.8 { actions.push("carefulSynthesis".to_string()); }
-        if 1.8 <= arg && arg < 2.0 {
-            actions.push("observe".to_string());
-            actions.push("focusedSynthesis".to_string());
-        }
         SolverResult{
             steps:craft.step_count,
             progression: craft.progression,
@@ -161,7 +499,17 @@ impl Parameters{
             threads: args.threads,
             verbose: args.verbose,
             desperate: args.desperate,
-            byregot_step: if args.long{6}else{8}
+            byregot_step: if args.long{6}else{8},
+            game_version: args.game_version,
+            action_table: None,
+            mode: args.mode,
+            opener: args.opener.clone(),
+            objective: args.objective,
+            beam_width: args.beam_width,
+            beam_heuristic: args.beam_heuristic,
+            seed: args.seed,
+            generations: args.generations,
+            population: args.population,
         }
     }
 }
@@ -175,6 +523,8 @@ impl Parameters{
 fn xiv_craft_solver(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(test_result, m)?)?;
     m.add_function(wrap_pyfunction!(solve_from_python, m)?)?;
+    m.add_function(wrap_pyfunction!(pareto_front_from_python, m)?)?;
+    m.add_class::<CancelToken>()?;
     Ok(())
 }
 
@@ -187,27 +537,80 @@ pub fn test_result()-> SolverResult {
 
 /// Create a stat struct stats with the base values
 #[cfg(not(feature="no_python"))]
-use crate::{Recipe,Stats,solve_craft};
+use crate::{Recipe,Stats,solve_craft_with,load_actions_file,load_from_config,recipe_level,pareto_front};
+#[cfg(not(feature="no_python"))]
+use crate::control::{CancelToken, Progress, ProgressCallback, SearchControl};
+#[cfg(not(feature="no_python"))]
+use crate::config::ConfigError;
 
+/// Config errors are raised as OSError when the file can't be read, ValueError otherwise
 #[cfg(not(feature="no_python"))]
-#[pyfunction]
-pub fn solve_from_python(values : &PyAny)->PyResult<Option<Vec<SolverResult>>>{
-    // // Create Recipe
-    let recipe = Recipe{
+impl From<ConfigError> for PyErr {
+    fn from(error: ConfigError) -> Self {
+        match error {
+            ConfigError::Io { .. } => pyo3::exceptions::PyOSError::new_err(error.to_string()),
+            _ => pyo3::exceptions::PyValueError::new_err(error.to_string()),
+        }
+    }
+}
+
+/// Get an attribute of the python object, or a default value when it doesn't have it
+#[cfg(not(feature="no_python"))]
+fn optional_attr<'p, T: FromPyObject<'p>>(values: &'p PyAny, name: &str, default: T) -> PyResult<T> {
+    match values.hasattr(name)? {
+        true => values.getattr(name)?.extract(),
+        false => Ok(default),
+    }
+}
+
+/// Read a recipe from the attributes of the python object
+#[cfg(not(feature="no_python"))]
+fn recipe_from_python(values: &PyAny) -> PyResult<Recipe> {
+    // The dividers and modifiers can come from the recipe level
+    let rlvl: u32 = optional_attr(values, "rlvl", 0)?;
+    let level = recipe_level::find(rlvl);
+    let level_attr = |name: &str, from_level: Option<u32>| -> PyResult<u32> {
+        match from_level {
+            Some(v) => optional_attr(values, name, v),
+            None => values.getattr(name)?.extract(),
+        }
+    };
+    Ok(Recipe{
         durability: values.getattr("durability")?.extract()?,
         progress: values.getattr("progress")?.extract()?, 
-        progress_divider: values.getattr("progress_divider")?.extract()?,
-        progress_modifier: values.getattr("progress_modifier")?.extract()?,
+        progress_divider: level_attr("progress_divider", level.map(|l| l.progress_divider))?,
+        progress_modifier: level_attr("progress_modifier", level.map(|l| l.progress_modifier))?,
         quality: values.getattr("quality")?.extract()?,
-        quality_divider:values.getattr("quality_divider")?.extract()?,
-        quality_modifier:values.getattr("quality_modifier")?.extract()?,
-    };
+        quality_divider: level_attr("quality_divider", level.map(|l| l.quality_divider))?,
+        quality_modifier: level_attr("quality_modifier", level.map(|l| l.quality_modifier))?,
+        expert: optional_attr(values, "expert", false)?,
+        rlvl,
+        class_job_level: optional_attr(values, "class_job_level", level.map_or(MAX_LEVEL, |l| l.class_job_level))?,
+    })
+}
 
-    // Create Stats
-    let stats = Stats{
+/// Read the stats of a character from the attributes of the python object
+#[cfg(not(feature="no_python"))]
+fn stats_from_python(values: &PyAny) -> PyResult<Stats> {
+    Ok(Stats{
         craftsmanship: values.getattr("craftsmanship")?.extract()?,
         control: values.getattr("control")?.extract()?,
         max_cp: values.getattr("max_cp")?.extract()?,
+        specialist: optional_attr(values, "specialist", false)?,
+        level: optional_attr(values, "level", MAX_LEVEL)?,
+    })
+}
+
+#[cfg(not(feature="no_python"))]
+#[pyfunction]
+pub fn solve_from_python(values : &PyAny)->PyResult<Option<Vec<SolverResult>>>{
+    // Recipe and stats are read from a config file when given one, from the object otherwise
+    let (recipe, stats) = match values.hasattr("config_file")? {
+        true => load_from_config(
+            values.getattr("recipe_name")?.extract()?,
+            values.getattr("config_file")?.extract()?,
+            values.getattr("character_name")?.extract()?)?,
+        false => (recipe_from_python(values)?, stats_from_python(values)?),
     };
 
     // Create parameters
@@ -215,11 +618,102 @@ pub fn solve_from_python(values : &PyAny)->PyResult<Option<Vec<SolverResult>>>{
         depth: values.getattr("depth")?.extract()?,
         byregot_step: values.getattr("byregot_step")?.extract()?,
         desperate: values.getattr("desperate")?.extract()?,
-        threads: values.getattr("threads")?.extract()?,
+        threads: optional_attr(values, "threads", 0)?,
         verbose: values.getattr("verbose")?.extract()?,
+        game_version: optional_attr(values, "game_version", "7".to_string())?.parse()
+            .map_err(pyo3::exceptions::PyValueError::new_err)?,
+        action_table: None,
+        mode: optional_attr(values, "mode", "phases".to_string())?.parse()
+            .map_err(pyo3::exceptions::PyValueError::new_err)?,
+        opener: optional_attr(values, "opener", "classic".to_string())?.parse()
+            .map_err(pyo3::exceptions::PyValueError::new_err)?,
+        objective: optional_attr::<Option<String>>(values, "objective", None)?.map(|o| o.parse()).transpose()
+            .map_err(pyo3::exceptions::PyValueError::new_err)?,
+        beam_width: optional_attr(values, "beam_width", 1000)?,
+        beam_heuristic: optional_attr(values, "beam_heuristic", "bound".to_string())?.parse()
+            .map_err(pyo3::exceptions::PyValueError::new_err)?,
+        seed: optional_attr(values, "seed", 0)?,
+        generations: optional_attr(values, "generations", 300)?,
+        population: optional_attr(values, "population", 200)?,
+    };
+    let param = match values.hasattr("actions_file")? {
+        true => Parameters{
+            action_table: Some(load_actions_file(values.getattr("actions_file")?.extract()?, param.game_version)?),
+            ..param
+        },
+        false => param,
     };
 
+    // The search can be stopped with a time limit in seconds or a CancelToken, and reports to a progress callable
+    let time_limit = optional_attr::<Option<f64>>(values, "time_limit", None)?.map(std::time::Duration::from_secs_f64);
+    let cancel = optional_attr::<Option<PyRef<CancelToken>>>(values, "cancel_token", None)?.map_or_else(CancelToken::default, |t| t.clone());
+    let progress = optional_attr::<Option<PyObject>>(values, "progress", None)?.map(|callback| {
+        Box::new(move |p: &Progress| Python::with_gil(|py| {
+            // An error of the callback shouldn't stop the search
+            let _ = callback.call1(py, (p.phase1_done, p.phase1_routes, p.nodes, p.best_quality));
+        })) as ProgressCallback
+    });
+    let control = SearchControl::new(time_limit, cancel, progress);
+    // Better routes are given to an optional callable as soon as they are found
+    if let Some(callback) = optional_attr::<Option<PyObject>>(values, "on_result", None)? {
+        control.set_result_callback(Some(Arc::new(move |result| Python::with_gil(|py| {
+            let _ = callback.call1(py, (result,));
+        }))));
+    }
+
     // println!("{:?} len: {:?} ",values,values.getattr("len()"));
-    let res = solve_craft(recipe, stats, param);
+    // The GIL is released so python keeps running, a GUI isn't frozen by the search
+    let res = values.py().allow_threads(|| solve_craft_with(recipe, stats, param, &control));
     Ok(res)
-}
\ No newline at end of file
+}
+
+/// Keep the results no other one beats on quality, steps, durability and cp, from the least steps to the most
+#[cfg(not(feature="no_python"))]
+#[pyfunction]
+pub fn pareto_front_from_python(results: Vec<PyRef<SolverResult>>) -> Vec<SolverResult> {
+    pareto_front(results.iter().map(|r| &**r)).into_iter().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_openers() {
+        assert_eq!("classic".parse::<Opener>(), Ok(Opener::Classic));
+        assert_eq!(" free ".parse::<Opener>(), Ok(Opener::Free));
+        let fixed = "reflect, manipulation".parse::<Opener>().unwrap();
+        assert_eq!(fixed.allowed_actions(0), Some(&[ActionId::Reflect][..]));
+        assert_eq!(fixed.allowed_actions(1), Some(&[ActionId::Manipulation][..]));
+        assert_eq!(fixed.allowed_actions(2), None);
+        let first = "first:muscle_memory,reflect".parse::<Opener>().unwrap();
+        assert_eq!(first.allowed_actions(0), Some(&[ActionId::MuscleMemory, ActionId::Reflect][..]));
+        assert_eq!(first.allowed_actions(1), None);
+        assert_eq!(Opener::Classic.allowed_actions(2), Some(&[ActionId::Veneration][..]));
+        assert!("reflect,reflekt".parse::<Opener>().unwrap_err().contains("'reflekt'"));
+        assert!("first:".parse::<Opener>().is_err());
+    }
+
+    #[test]
+    fn parses_objectives() {
+        assert_eq!("least_steps".parse::<Objective>(), Ok(Objective::LeastSteps));
+        assert_eq!("least_cp".parse::<Objective>(), Ok(Objective::LeastCp));
+        assert_eq!("most_durability".parse::<Objective>(), Ok(Objective::MostDurability));
+        assert_eq!("most_quality: 20".parse::<Objective>(), Ok(Objective::MostQuality(20)));
+        assert_eq!("weighted:1,-100,0.5,5".parse::<Objective>(),
+            Ok(Objective::Weighted(ScoreWeights { quality: 1.0, steps: -100.0, cp: 0.5, durability: 5.0 })));
+        assert!("most_quality".parse::<Objective>().is_err());
+        assert!("weighted:1,2,3".parse::<Objective>().is_err());
+        assert!("most_steps".parse::<Objective>().is_err());
+    }
+
+    #[test]
+    fn parses_beam_heuristics() {
+        assert_eq!("bound".parse::<BeamHeuristic>(), Ok(BeamHeuristic::Bound));
+        assert_eq!("progress".parse::<BeamHeuristic>(), Ok(BeamHeuristic::Progress));
+        assert_eq!("weighted:1,0,2,5".parse::<BeamHeuristic>(),
+            Ok(BeamHeuristic::Weighted(ScoreWeights { quality: 1.0, steps: 0.0, cp: 2.0, durability: 5.0 })));
+        assert!("weighted".parse::<BeamHeuristic>().is_err());
+        assert!("quality".parse::<BeamHeuristic>().is_err());
+    }
+}
@@ -1,155 +1,160 @@
 use crate::io::SolverResult;
 use crate::{
+    action::ActionList,
+    backend::Solver,
+    config::{ConfigError, ConfigTable},
     craft::Craft,
-    specs::{Recipe,Stats},
+    specs::{GameVersion,Recipe,Stats,MAX_LEVEL},
     io::Parameters,
 };
-use threadpool::ThreadPool;
-use std::sync::{Arc,Mutex};
+use crate::control::SearchControl;
+use crate::policy::{Policy, PolicySolver};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
 
 mod solver;
-mod specs;
+mod exact;
+mod bound;
+mod node;
+mod genetic;
+pub mod specs;
+pub mod recipe_level;
+pub mod recipe_db;
+pub mod config;
 pub mod action;
 pub mod craft;
 pub mod io;
+pub mod control;
+pub mod backend;
+pub mod policy;
 
 /// Solve the craft with given arguments, this functions calls threads and must own it's values
 pub fn solve_craft<'a>(recipe: Recipe, stats: Stats, params: Parameters) -> Option<Vec<SolverResult>>{
-    // Load the craft with given arguments
-    let craft = Craft::new(recipe,stats,params);
-
-    // Start a threadpool
-    let pool = ThreadPool::new(params.threads);
-
-    #[cfg(feature = "verbose")]
-    if params.verbose>0{
-        println!("[P1] Starting phase 1...");
-    }
-
-    let phase1_routes = solver::generate_routes_phase1(craft);
-    let nb_p1 = phase1_routes.len();
+    solve_craft_with(recipe, stats, params, &SearchControl::default())
+}
 
-    #[cfg(feature = "verbose")]
-    if params.verbose>0{
-        println!("[P1] Found {} routes, testing them all...",phase1_routes.len());
-        if params.verbose>1{ for r in &phase1_routes{
-            println!("[P1] {:?} p:{}% q:{}% c:{} d:{}",r.actions,r.progression,r.quality * 100 / r.recipe.quality,r.cp,r.durability);
-        }}
-    }
+/// Solve the craft under a time limit, a cancellation token and a progress callback
+/// When stopped, the best routes found so far are returned
+pub fn solve_craft_with(recipe: Recipe, stats: Stats, params: Parameters, control: &SearchControl) -> Option<Vec<SolverResult>>{
+    solve_craft_using(params.mode.solver().as_ref(), recipe, stats, params, control)
+}
 
-    // Core algorithm, fill all found routes with the best route (doesn't branch, just replace)
-    let arc_phase2_routes = Arc::new(Mutex::new(Vec::<Craft>::new()));
+/// Solve the craft with a given solver instead of the one of the search mode, to try other algorithms
+pub fn solve_craft_using(solver: &dyn Solver, recipe: Recipe, stats: Stats, params: Parameters, control: &SearchControl) -> Option<Vec<SolverResult>>{
+    // Load the craft with given arguments, it borrows the actions of the file from this copy of the table
+    let action_table = params.action_table.clone();
+    let actions = action_table.as_deref().unwrap_or(params.game_version.actions());
+    let craft = Craft::with_actions(recipe,stats,params,actions);
+    solver.solve(craft, control)
+}
 
-    for route in phase1_routes {
-        let _phase2_routes = Arc::clone(&arc_phase2_routes);
+/// Find the action giving the most expected quality for each state and condition of the craft
+/// When stopped, the policy is the best one found so far and the states not searched yet are missing from it
+/// The policy borrows the actions of the parameters
+pub fn solve_policy<'a>(recipe: Recipe, stats: Stats, params: &'a Parameters, control: &SearchControl) -> Policy<'a> {
+    let actions = params.action_table.as_deref().unwrap_or(params.game_version.actions());
+    let craft = Craft::with_actions(recipe, stats, params.clone(), actions);
+    PolicySolver::new(&craft, control).solve(craft)
+}
 
-        pool.execute(move || {
-            if let Some(mut _route) = solver::generate_routes_phase2(route){
-                let mut shared = _phase2_routes.lock().unwrap();
-                shared.append(&mut _route);
-            };
-        });
-    }
+/// A search running on its own thread, iterating over the routes better than the ones before as soon as they are found
+/// The iteration ends with the search, its full results are then given by `join`
+pub struct SolveStream {
+    receiver: mpsc::Receiver<SolverResult>,
+    handle: JoinHandle<Option<Vec<SolverResult>>>,
+}
 
-    pool.join();
-    let phase2_routes = arc_phase2_routes.lock().unwrap();
-    let nb_p2 = phase2_routes.len();
+impl Iterator for SolveStream {
+    type Item = SolverResult;
 
-    // Drop on empty results
-    if phase2_routes.len()==0{
-        return None
+    fn next(&mut self) -> Option<SolverResult> {
+        self.receiver.recv().ok()
     }
+}
 
-    // Print the results if verbose
-    #[cfg(feature = "verbose")]
-    if params.verbose>0{
-        println!("[P2] Found {} solutions, sorting",phase2_routes.len());
-        if params.verbose>1{ for r in phase2_routes.iter(){
-                println!("[P2] {:?} p:{}% q:{}% d:{}", r.actions, r.progression * 100 / r.recipe.progress, r.quality * 100 / r.recipe.quality, r.durability);
-        }}
+impl SolveStream {
+    /// Wait for the end of the search and get the same results as `solve_craft_with`
+    pub fn join(self) -> Option<Vec<SolverResult>> {
+        self.handle.join().unwrap()
     }
+}
 
-    // Prune the results for analysis
-    // let mut valid_routes : Vec<Craft> = vec![];
-    let mut valid_solutions: Vec<SolverResult> = vec![];
-    for route in phase2_routes.iter(){
-        if route.quality>=route.recipe.quality{
-            // valid_routes.push(route.clone());
-            valid_solutions.push(SolverResult::from_craft(route,nb_p1,nb_p2,true));
+/// Solve the craft on another thread and stream the better routes as they are found, see `SolveStream`
+/// A result callback already set on the control is still called before each route is streamed, and set back at the end
+pub fn solve_craft_stream(recipe: Recipe, stats: Stats, params: Parameters, control: SearchControl) -> SolveStream {
+    let (sender, receiver) = mpsc::channel();
+    let previous = control.set_result_callback(None);
+    let chained = previous.clone();
+    control.set_result_callback(Some(Arc::new(move |result: SolverResult| {
+        if let Some(previous) = &chained {
+            previous(result.clone());
         }
-    }
-    // If no craft can make it to 100% HQ, fallback to base results
-    if valid_solutions.len()==0{
-        for route in phase2_routes.iter(){valid_solutions.push(SolverResult::from_craft(route,nb_p1,nb_p2,false));}
-    }
-
-    Some(valid_solutions)
+        // The stream may have been dropped, the search goes on all the same
+        let _ = sender.send(result);
+    })));
+    let handle = std::thread::spawn(move || {
+        let results = solve_craft_with(recipe, stats, params, &control);
+        // Dropping the sender ends the iteration
+        control.set_result_callback(previous);
+        results
+    });
+    SolveStream { receiver, handle }
 }
 
 /// Load the config from args and make a craft from it
-pub fn load_from_config<'a>(recipe_name: &str, file_name: &str, character_name: &str) -> (Recipe,Stats) {
-    //read craft.toml
-    let config: toml::Value = toml::from_str(
-        &std::fs::read_to_string(file_name)
-        .expect(&format!("Can't open {}",file_name))
-        ).unwrap();
-
-    let recp = match config.get(recipe_name){
-        Some(r) => r,
-        None => panic!("Can't find value '{}' in '{}'",recipe_name, file_name)
+pub fn load_from_config(recipe_name: &str, file_name: &str, character_name: &str) -> Result<(Recipe,Stats), ConfigError> {
+    Ok((load_recipe_from_config(recipe_name, file_name)?, load_stats_from_config(character_name, file_name)?))
+}
+
+/// Load a recipe from the config file
+pub fn load_recipe_from_config(recipe_name: &str, file_name: &str) -> Result<Recipe, ConfigError> {
+    let config = config::read_file(file_name)?;
+    let recp = ConfigTable::new(&config, file_name, recipe_name)?;
+
+    // The dividers and modifiers can be given by hand, or found from the recipe level
+    let rlvl = recp.optional_integer("rlvl")?;
+    let level = rlvl.and_then(recipe_level::find);
+    let level_value = |key: &str, from_level: Option<u32>| -> Result<u32, ConfigError> {
+        match (recp.optional_integer(key)?, from_level) {
+            (Some(v), _) | (None, Some(v)) => Ok(v),
+            // The level is given but the built-in table doesn't know it
+            (None, None) if rlvl.is_some() => Err(recp.invalid_value("rlvl", "a level of the built-in recipe level table")),
+            (None, None) => Err(recp.missing_key(key)),
+        }
     };
 
     // Load receipe values
-    let recipe = Recipe {
-        durability: recp
-            .get("durability").expect(&format!("Can't find 'durability' in recipe '{}' on file '{}'",
-                recipe_name,file_name))
-            .as_integer().expect("Can't convert durability as an integer") as u32,
-        progress: recp
-            .get("progress").expect(&format!("Can't find 'progress' in recipe '{}' on '{}'",
-                recipe_name,file_name))
-            .as_integer().expect("Can't convert progress as an integer") as u32,
-        quality: recp
-            .get("quality").expect(&format!("Can't find 'quality' in recipe '{}' on '{}'",
-                recipe_name,file_name))
-            .as_integer().expect("Can't convert quality as an integer") as u32,
-        progress_divider: recp
-            .get("progress_divider").expect(&format!("Can't find 'progress_divider' in recipe '{}' on '{}'",
-                recipe_name,file_name))
-            .as_integer().expect("Can't convert progress_divider as an integer") as u32,
-        quality_divider: recp
-            .get("quality_divider").expect(&format!("Can't find 'quality_divider' in recipe '{}' on '{}'",
-                recipe_name,file_name))
-            .as_integer().expect("Can't convert quality_divider as an integer") as u32,
-        progress_modifier: recp
-            .get("progress_modifier").expect(&format!("Can't find 'progress_modifier' in recipe '{}' on '{}'",
-                recipe_name,file_name))
-            .as_integer().expect("Can't convert progress_modifier as an integer") as u32,
-        quality_modifier: recp
-            .get("quality_modifier").expect(&format!("Can't find 'quality_modifier' in recipe '{}' on '{}'",
-                recipe_name,file_name))
-            .as_integer().expect("Can't convert quality_modifier as an integer") as u32,
-    };
+    Ok(Recipe {
+        durability: recp.integer("durability")?,
+        progress: recp.integer("progress")?,
+        quality: recp.integer("quality")?,
+        progress_divider: level_value("progress_divider", level.map(|l| l.progress_divider))?,
+        quality_divider: level_value("quality_divider", level.map(|l| l.quality_divider))?,
+        progress_modifier: level_value("progress_modifier", level.map(|l| l.progress_modifier))?,
+        quality_modifier: level_value("quality_modifier", level.map(|l| l.quality_modifier))?,
+        expert: recp.optional_bool("expert")?.unwrap_or(false),
+        rlvl: rlvl.unwrap_or(0),
+        class_job_level: level_value("class_job_level", Some(level.map_or(MAX_LEVEL, |l| l.class_job_level)))?,
+    })
+}
 
-    let cfg = match config.get(character_name){
-        Some(c) => c,
-        None => panic!("Can't find '{}' in file '{}'",character_name,file_name),
-    };
-    let stats = Stats {
-        craftsmanship: cfg
-            .get("craftsmanship").expect(&format!("Can't find 'craftsmanship' in character '{}' on file '{}'",
-                character_name,file_name))
-            .as_integer().expect("Can't convert craftsmanship as an integer") as u32,
-        control: cfg
-            .get("control").expect(&format!("Can't find 'control' in character '{}' on file '{}'",
-                character_name,file_name))
-            .as_integer().expect("Can't convert control as an integer") as u32,
-        max_cp: cfg
-            .get("max_cp").expect(&format!("Can't find 'max_cp' in character '{}' on file '{}'",
-                character_name,file_name))
-            .as_integer().expect("Can't convert max_cp as an integer") as u32,
-    };
-    (recipe,stats)
+/// Load the stats of a character from the config file
+pub fn load_stats_from_config(character_name: &str, file_name: &str) -> Result<Stats, ConfigError> {
+    let config = config::read_file(file_name)?;
+    let cfg = ConfigTable::new(&config, file_name, character_name)?;
+    Ok(Stats {
+        craftsmanship: cfg.integer("craftsmanship")?,
+        control: cfg.integer("control")?,
+        max_cp: cfg.integer("max_cp")?,
+        specialist: cfg.optional_bool("specialist")?.unwrap_or(false),
+        level: cfg.optional_integer("level")?.unwrap_or(MAX_LEVEL),
+    })
+}
+
+/// Load an action table from a file, patching the actions of the given game version
+/// The table is shared by the parameters and the solver threads, and freed with the last of them
+pub fn load_actions_file(file_name: &str, game_version: GameVersion) -> Result<Arc<ActionList>, ConfigError> {
+    Ok(Arc::new(ActionList::from_file(file_name, game_version.actions())?))
 }
 
 /// Print all routes in the vect, verbose
@@ -165,6 +170,35 @@ pub fn print_routes<'a>(routes: & Option<Vec<SolverResult>>){
     }
 }
 
+/// Keep the routes no other route beats on quality (up to the recipe one), steps, durability and cp left,
+/// sorted from the least steps to the most then from the most quality to the least
+pub fn pareto_front<'a>(routes: impl IntoIterator<Item = &'a SolverResult>) -> Vec<&'a SolverResult> {
+    let values = |r: &SolverResult| (r.quality.min(r.total_quality), r.steps, r.durability, r.cp);
+    // Routes with the same values are beaten by the first one
+    let beats = |a: &SolverResult, b: &SolverResult| {
+        let (a, b) = (values(a), values(b));
+        a.0 >= b.0 && a.1 <= b.1 && a.2 >= b.2 && a.3 >= b.3
+    };
+    let mut front: Vec<&SolverResult> = Vec::new();
+    for route in routes {
+        if front.iter().any(|r| beats(r, route)) {
+            continue;
+        }
+        front.retain(|r| !beats(route, r));
+        front.push(route);
+    }
+    front.sort_by_key(|r| (r.steps, std::cmp::Reverse(r.quality), std::cmp::Reverse(r.durability)));
+    front
+}
+
+/// Find the routes of the pareto front, see `pareto_front`
+pub fn find_pareto_routes(routes: &Option<Vec<SolverResult>>) -> Vec<&SolverResult> {
+    match routes {
+        Some(_routes) => pareto_front(_routes),
+        None => vec![],
+    }
+}
+
 /// Find the route with the least amount of steps
 pub fn find_fast_route(routes: &Option<Vec<SolverResult>>) -> Option<&SolverResult>{
     match routes {
@@ -197,4 +231,129 @@ pub fn find_safe_route(routes: &Option<Vec<SolverResult>>) -> Option<&SolverResu
         },
         None => None,
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use crate::craft::fixtures::{params, stats};
+    use super::*;
+
+    #[test]
+    fn loads_actions_files() {
+        let file = TempConfig::new("actions", "[groundwork]\ncp = 20\n");
+        let actions = load_actions_file(file.name(), GameVersion::Endwalker).unwrap();
+        assert_eq!((actions.groundwork.cp, actions.reflect.quality), (20, 100));
+        // An edited file gives a new table, the old one is freed with its last user
+        std::fs::write(&file.0, "[groundwork]\ncp = 22\n").unwrap();
+        assert_eq!(load_actions_file(file.name(), GameVersion::Dawntrail).unwrap().groundwork.cp, 22);
+        assert_eq!(Arc::strong_count(&actions), 1);
+    }
+
+    fn route(quality: u32, steps: u32, durability: i32, cp: i32) -> SolverResult {
+        SolverResult { quality, steps, durability, cp, total_quality: 1000, ..SolverResult::default() }
+    }
+
+    #[test]
+    fn keeps_the_routes_not_beaten() {
+        let routes = [
+            route(1000, 10, 10, 5),
+            // Beaten by the first one on every value
+            route(900, 11, 10, 5),
+            // The quality over the recipe one doesn't count
+            route(1200, 10, 10, 5),
+            route(1000, 9, 0, 0),
+            route(800, 8, 20, 30),
+            route(1000, 10, 15, 5),
+        ];
+        let front = pareto_front(&routes).into_iter().map(|r| (r.quality, r.steps, r.durability)).collect::<Vec<_>>();
+        assert_eq!(front, [(800, 8, 20), (1000, 9, 0), (1000, 10, 15)]);
+        assert!(pareto_front(&[]).is_empty());
+    }
+
+    /// Write a config file for a test, removed when the guard is dropped
+    struct TempConfig(std::path::PathBuf);
+
+    impl TempConfig {
+        fn new(name: &str, content: &str) -> Self {
+            let file = std::env::temp_dir().join(format!("{}_{}.toml", name, std::process::id()));
+            std::fs::write(&file, content).unwrap();
+            Self(file)
+        }
+
+        fn name(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempConfig {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn loads_recipes_and_characters() {
+        let config = TempConfig::new("config_ok", "[by_level]\nrlvl = 580\ndurability = 70\nprogress = 3900\nquality = 10920\n\n\
+            [by_hand]\ndurability = 80\nprogress = 100\nquality = 200\nprogress_divider = 50\nquality_divider = 30\n\
+            progress_modifier = 100\nquality_modifier = 100\nclass_job_level = 10\nexpert = true\n\n\
+            [crafter]\ncraftsmanship = 4041\ncontrol = 3959\nmax_cp = 602\nlevel = 95\n");
+        let (recipe, stats) = load_from_config("by_level", config.name(), "crafter").unwrap();
+        assert_eq!((recipe.progress_divider, recipe.quality_modifier, recipe.class_job_level), (130, 70, 90));
+        assert_eq!((stats.max_cp, stats.level, stats.specialist), (602, 95, false));
+        let recipe = load_recipe_from_config("by_hand", config.name()).unwrap();
+        assert_eq!((recipe.progress_divider, recipe.class_job_level, recipe.rlvl, recipe.expert), (50, 10, 0, true));
+    }
+
+    #[test]
+    fn reports_config_errors() {
+        assert!(matches!(load_recipe_from_config("recipe", "/nonexistent/craft.toml"), Err(ConfigError::Io { .. })));
+        let config = TempConfig::new("config_bad", "[recipe\n");
+        assert!(matches!(load_recipe_from_config("recipe", config.name()), Err(ConfigError::Parse { .. })));
+
+        let config = TempConfig::new("config_errors", "not_a_table = 1\n\
+            [no_divider]\ndurability = 70\nprogress = 3900\nquality = 10920\n\n\
+            [unknown_level]\nrlvl = 1234\ndurability = 70\nprogress = 3900\nquality = 10920\n\n\
+            [negative]\nrlvl = 580\ndurability = -70\nprogress = 3900\nquality = 10920\n\n\
+            [crafter]\ncraftsmanship = 4041\ncontrol = 3959\nmax_cp = 602\nspecialist = \"yes\"\n");
+        let error = |recipe: &str| load_recipe_from_config(recipe, config.name()).unwrap_err();
+        assert!(matches!(error("missing"), ConfigError::MissingTable { table, .. } if table == "missing"));
+        assert!(matches!(error("not_a_table"), ConfigError::InvalidValue { expected: "a table", .. }));
+        assert!(matches!(error("no_divider"), ConfigError::MissingKey { key, .. } if key == "progress_divider"));
+        assert!(matches!(error("unknown_level"), ConfigError::InvalidValue { key, .. } if key == "rlvl"));
+        assert!(matches!(error("negative"), ConfigError::InvalidValue { key, .. } if key == "durability"));
+        let error = load_stats_from_config("crafter", config.name()).unwrap_err();
+        assert!(matches!(&error, ConfigError::InvalidValue { key, expected: "a boolean", .. } if key == "specialist"));
+        assert!(error.to_string().starts_with("'specialist' in 'crafter' on '"), "{}", error);
+    }
+
+    #[test]
+    fn stopped_searches_still_give_a_route() {
+        let recipe = Recipe::from_rlvl(580, 3900, 10920, 70).unwrap();
+        let stats = stats(602);
+        for mode in [io::SearchMode::Unified, io::SearchMode::Exact] {
+            let mut params = params();
+            params.mode = mode;
+            let control = SearchControl::new(Some(std::time::Duration::ZERO), control::CancelToken::default(), None);
+            let routes = solve_craft_with(recipe, stats, params, &control).unwrap_or_default();
+            assert!(control.is_stopped());
+            assert!(routes.iter().any(|route| route.progression >= recipe.progress), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn stream_keeps_the_callback_set_before() {
+        let (recipe, stats) = load_from_config("three_star_35", "craft.toml", "default_character").unwrap();
+        let params = params();
+        let control = SearchControl::default();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let before = Arc::clone(&seen);
+        let previous: control::ResultCallback = Arc::new(move |result: SolverResult| before.lock().unwrap().push(result.quality));
+        control.set_result_callback(Some(previous));
+        let mut stream = solve_craft_stream(recipe, stats, params, control.clone());
+        let streamed: Vec<u32> = stream.by_ref().map(|result| result.quality).collect();
+        assert!(stream.join().is_some());
+        assert!(!streamed.is_empty());
+        assert_eq!(*seen.lock().unwrap(), streamed);
+        assert!(control.set_result_callback(None).is_some());
+    }
+}
@@ -0,0 +1,85 @@
+use crate::action::ActionId;
+use crate::craft::{Craft, CraftState};
+
+/// Index of a node in its arena
+pub type NodeId = u32;
+
+/// A craft reached by a search, without its recipe, stats, parameters or actions
+/// The actions are found back by following the parents up to the root
+#[derive(Debug, Clone, Copy)]
+pub struct Node {
+    pub state: CraftState,
+    /// The node it was reached from and the action run on it, none for the root
+    pub parent: Option<(NodeId, ActionId)>,
+}
+
+/// The nodes a search reached, a node is never removed so its id stays valid until the arena is dropped
+#[derive(Debug, Default)]
+pub struct NodeArena {
+    nodes: Vec<Node>,
+}
+
+impl NodeArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the node the search starts from
+    pub fn root(&mut self, state: CraftState) -> NodeId {
+        self.add(Node { state, parent: None })
+    }
+
+    /// Add the node reached by running an action on another one
+    pub fn push(&mut self, state: CraftState, parent: NodeId, action: ActionId) -> NodeId {
+        self.add(Node { state, parent: Some((parent, action)) })
+    }
+
+    fn add(&mut self, node: Node) -> NodeId {
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(node);
+        id
+    }
+
+    /// Forget the nodes added after this one
+    /// A depth first search adding its nodes in the order they are stacked no longer needs them once it pops this one
+    pub fn truncate(&mut self, id: NodeId) {
+        self.nodes.truncate(id as usize + 1);
+    }
+
+    /// The ids of the nodes still in the arena
+    pub fn ids(&self) -> std::ops::Range<NodeId> {
+        0..self.nodes.len() as NodeId
+    }
+
+    pub fn state(&self, id: NodeId) -> CraftState {
+        self.nodes[id as usize].state
+    }
+
+    /// The actions run from the root to the node
+    pub fn actions(&self, mut id: NodeId) -> Vec<ActionId> {
+        let mut actions = Vec::new();
+        while let Some((parent, action)) = self.nodes[id as usize].parent {
+            actions.push(action);
+            id = parent;
+        }
+        actions.reverse();
+        actions
+    }
+
+    /// Put a craft in the state of the node, with no actions, to simulate the next ones from it
+    pub fn load<'a>(&self, craft: &mut Craft<'a>, id: NodeId) {
+        craft.set_state(self.state(id));
+        craft.actions.clear();
+    }
+
+    /// The craft of the node with its full actions, the root being the given craft
+    /// The actions of the tail, like the ones finishing the route, are added after them and its state is kept
+    pub fn route<'a>(&self, root: &Craft<'a>, id: NodeId, tail: &Craft<'a>) -> Craft<'a> {
+        let mut route = tail.clone();
+        let actions = route.action_list();
+        route.actions = root.actions.clone();
+        route.actions.extend(self.actions(id).into_iter().map(|id| actions.get(id)));
+        route.actions.extend(&tail.actions);
+        route
+    }
+}
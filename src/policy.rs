@@ -0,0 +1,508 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::sync::Mutex;
+use strum::IntoEnumIterator;
+use crate::action::{Action, ActionId};
+use crate::bound::QualityBound;
+use crate::control::{SearchControl, CHECK_INTERVAL};
+use crate::craft::{Craft, CraftState};
+use crate::genetic::Rng;
+use crate::io::Opener;
+use crate::solver::{next_action_phases, remaining_progression, Finisher};
+use crate::specs::{Buff, Condition, Success};
+
+/// Values closer than this are a tie, the first action found is then kept
+const EPSILON: f64 = 1e-9;
+/// Quality taken off for each step, so that of the actions giving the same quality the ones ending the craft sooner
+/// are kept
+const STEP_COST: f64 = 0.01;
+/// Most change of its value for a state to be solved, in quality points
+const RESIDUAL: f64 = 0.001;
+/// Most steps of a trial, a craft gaining back its cp with Tricks of the Trade could go on for long
+const MAX_TRIAL_STEPS: usize = 200;
+/// Steps after which the actions the searches allow no longer depend on the step
+const STEP_RULES: u32 = 9;
+
+/// A state of the craft as the policy sees it, see `key`, with whether Byregot's Blessing was used
+type Key = (CraftState, Condition, bool);
+
+/// What the search knows of a state
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    /// Expected quality still to gain until the target when following the policy, minus infinity when the craft
+    /// can't be finished
+    value: f64,
+    /// Best action for the value, none when no action finishes the craft
+    action: Option<ActionId>,
+    /// Whether the values of the states the policy can reach from it no longer change
+    solved: bool,
+}
+
+/// A line of the decision table
+#[derive(Debug, Clone)]
+pub struct PolicyRow {
+    pub state: CraftState,
+    pub condition: Condition,
+    pub action: ActionId,
+    /// Quality expected at the end of the craft when running the action, up to the target of the recipe
+    pub expected_quality: f64,
+    /// Chance of the craft reaching this state with this condition when following the policy
+    pub reach: f64,
+}
+
+/// The action giving the most expected quality for each state and condition of the craft, the quality over the target
+/// of the recipe counting for nothing
+pub struct Policy<'a> {
+    /// The craft the policy starts from
+    craft: Craft<'a>,
+    entries: HashMap<Key, Entry>,
+    /// Finishes the states the search didn't reach
+    finisher: Mutex<Finisher<'a>>,
+    /// Quality expected at the end of the craft from each state the policy reaches
+    expected: HashMap<Key, f64>,
+    step_cap: u32,
+    /// Quality expected at the end of the craft when following the policy, up to the target of the recipe
+    pub expected_quality: f64,
+    /// Whether the policy is the best one, otherwise the search was stopped before and the states it didn't
+    /// search are finished with the synthesis when they can be, and are missing from the table otherwise
+    pub complete: bool,
+    pub nb_states: usize,
+}
+
+impl<'a> Policy<'a> {
+    /// The action to run in a state of the craft under a condition, none when the craft can't be finished from it
+    /// No touch is allowed once Byregot's Blessing was used, like in the other searches
+    pub fn action(&self, state: &CraftState, condition: Condition, byregot_used: bool) -> Option<ActionId> {
+        self.table_action(key(*state, condition, byregot_used, self.step_cap, self.craft.recipe.quality))
+    }
+
+    /// The action of a state, the first synthesis finishing it when it wasn't searched
+    fn table_action(&self, state_key: Key) -> Option<ActionId> {
+        match self.entries.get(&state_key) {
+            Some(entry) => entry.action,
+            None => self.finisher.lock().unwrap().finish(state_key.0).and_then(|(_, ids)| ids.first().copied()),
+        }
+    }
+
+    /// The states the craft can reach when following the policy, with the action to run in each of them,
+    /// from the first step to the last one
+    pub fn rows(&self) -> Vec<PolicyRow> {
+        let mut scratch = self.craft.clone();
+        let actions = scratch.action_list();
+        let target = self.craft.recipe.quality;
+        let mut rows = Vec::new();
+        // The states of a step with their chance, the same state is reached by many paths
+        let mut layer = HashMap::from([(key(self.craft.state(), Condition::Normal, false, u32::MAX, target), 1.0)]);
+        for _ in 0..MAX_TRIAL_STEPS {
+            if layer.is_empty() {
+                break;
+            }
+            let mut next: HashMap<Key, f64> = HashMap::new();
+            let mut states = layer.into_iter().collect::<Vec<_>>();
+            // Sorted so the table is the same from one run to the other
+            states.sort_by_cached_key(|((state, condition, _), _)|
+                (state.progression, state.quality, state.durability, state.cp, buff_list(state), *condition as u8));
+            for ((state, condition, byregot_used), reach) in states {
+                let policy_key = key(state, condition, byregot_used, self.step_cap, target);
+                let action = match self.table_action(policy_key) {
+                    Some(action) => action,
+                    None => continue,
+                };
+                let expected_quality = self.expected.get(&policy_key).copied().unwrap_or_default();
+                rows.push(PolicyRow { state, condition, action, expected_quality, reach });
+                let child = run(&mut scratch, state, condition, actions.get(action));
+                if child.success != Success::Pending {
+                    continue;
+                }
+                let byregot_used = byregot_used || action == ActionId::ByregotBlessing;
+                for &(next_condition, chance) in self.craft.recipe.next_conditions(condition) {
+                    *next.entry(key(child, next_condition, byregot_used, u32::MAX, target)).or_default() += reach * chance;
+                }
+            }
+            layer = next;
+        }
+        rows
+    }
+
+    /// Quality expected at the end of the craft from a state when following the policy, up to the target of the recipe,
+    /// a craft that can't be finished ending with none
+    fn expected_from(&self, state_key: Key, scratch: &mut Craft<'a>, expected: &mut HashMap<Key, f64>) -> f64 {
+        if let Some(&quality) = expected.get(&state_key) {
+            return quality;
+        }
+        // A state the policy leads back to isn't on the way of a finished craft
+        expected.insert(state_key, 0.0);
+        let (state, condition, byregot_used) = state_key;
+        let target = self.craft.recipe.quality;
+        let quality = match self.table_action(state_key) {
+            Some(action) => {
+                let child = run(scratch, state, condition, self.craft.action_list().get(action));
+                let byregot_used = byregot_used || action == ActionId::ByregotBlessing;
+                match child.success {
+                    Success::Success => child.quality.min(target) as f64,
+                    Success::Failure => 0.0,
+                    Success::Pending => self.craft.recipe.next_conditions(condition).iter()
+                        .map(|&(next, chance)| chance * self.expected_from(key(child, next, byregot_used, self.step_cap, target), scratch, expected))
+                        .sum(),
+                }
+            },
+            None => 0.0,
+        };
+        expected.insert(state_key, quality);
+        quality
+    }
+
+    /// Write the decision table of the craft as csv, one line per state the policy can reach
+    pub fn write_csv(&self, mut writer: impl Write) -> std::io::Result<()> {
+        let actions = self.craft.action_list();
+        writeln!(writer, "step,condition,progression,quality,durability,cp,buffs,action,expected_quality,reach")?;
+        for row in self.rows() {
+            let state = &row.state;
+            writeln!(writer, "{},{:?},{},{},{},{},{},{},{:.1},{:.6}", state.step_count, row.condition, state.progression,
+                state.quality, state.durability, state.cp, buff_list(state), actions.get(row.action).name,
+                row.expected_quality, row.reach)?;
+        }
+        Ok(())
+    }
+}
+
+/// The buffs of a state as `InnerQuiet:3 Innovation:2`, along with the uses of the limited actions
+fn buff_list(state: &CraftState) -> String {
+    let buffs = &state.buffs;
+    let uses = [("CarefulObservationUsed", buffs.careful_observation), ("HeartAndSoulUsed", buffs.heart_and_soul_used),
+        ("TrainedPerfectionUsed", buffs.trained_perfection_used), ("QuickInnovationUsed", buffs.quick_innovation_used)];
+    Buff::iter().map(|buff| (format!("{:?}", buff), buffs.get(buff)))
+        .chain(uses.into_iter().map(|(name, value)| (name.to_string(), value)))
+        .filter(|(_, value)| *value > 0)
+        .map(|(name, value)| format!("{}:{}", name, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The state as the policy sees it: the quality over the target counts for nothing, and the steps only matter
+/// for the actions allowed
+fn key(mut state: CraftState, condition: Condition, byregot_used: bool, step_cap: u32, target: u32) -> Key {
+    state.quality = state.quality.min(target);
+    state.step_count = state.step_count.min(step_cap);
+    (state, condition, byregot_used)
+}
+
+/// The state an action leads to from a state under a condition
+fn run<'a>(craft: &mut Craft<'a>, state: CraftState, condition: Condition, action: &'a Action) -> CraftState {
+    craft.set_state(state);
+    craft.actions.clear();
+    craft.run_action(action, condition);
+    craft.state()
+}
+
+/// Search of the action giving the most expected quality for each state and condition the craft can reach,
+/// the conditions being rolled by the game with their usual chances
+///
+/// It uses the actions of both phases, along with the ones needing a good condition when the condition
+/// allows them and the first action of the finisher once the progression is within the last synthesis.
+/// Like the other searches it only uses the actions that can't fail, and never the ones that would break the item.
+/// The states are valued with labeled real time dynamic programming: crafts are simulated from the start, rolling
+/// the conditions from the seed and running the best action for the values known so far, and the states they went
+/// through are valued again from the states their actions lead to. The states not reached yet are valued with
+/// the quality bound of the crafts under their best conditions, so they are never valued under what they can really
+/// gain, but for the cp Tricks of the Trade gives back on a good condition. A state is solved once the values of the
+/// states its best actions can lead to no longer change, and the search ends when the start is. Only the states the
+/// best actions can reach are searched. When stopped, the states that weren't searched are finished with the synthesis
+/// when they can be.
+pub struct PolicySolver<'a> {
+    /// Craft used to simulate the actions, its actions are cleared before each one
+    scratch: Craft<'a>,
+    /// Actions needing a good condition
+    condition_actions: Vec<&'a Action>,
+    /// Steps after which the actions allowed no longer depend on the step, the later ones are merged
+    step_cap: u32,
+    bound: QualityBound,
+    finisher: Finisher<'a>,
+    /// Quality of the recipe, the policy gains nothing over it
+    target: u32,
+    entries: HashMap<Key, Entry>,
+    rng: Rng,
+    control: SearchControl,
+    stopped: bool,
+    pub nb_updates: usize,
+}
+
+impl<'a> PolicySolver<'a> {
+    pub fn new(craft: &Craft<'a>, control: &SearchControl) -> Self {
+        let mut scratch = craft.clone();
+        scratch.actions.clear();
+        Self {
+            condition_actions: craft.action_list().iter().filter(|a| a.needs_good_condition()).collect(),
+            step_cap: match &craft.args.opener {
+                Opener::Fixed(ids) => STEP_RULES.max(ids.len() as u32),
+                _ => STEP_RULES,
+            },
+            bound: QualityBound::with_conditions(craft),
+            finisher: Finisher::new(craft),
+            target: craft.recipe.quality,
+            entries: HashMap::new(),
+            rng: Rng(craft.args.seed),
+            control: control.clone(),
+            stopped: false,
+            nb_updates: 0,
+            scratch,
+        }
+    }
+
+    /// Search the policy from the craft, the best one found so far when stopped
+    pub fn solve(mut self, craft: Craft<'a>) -> Policy<'a> {
+        let start = self.key(craft.state(), Condition::Normal, false);
+        while !self.is_solved(start) && !self.stopped {
+            self.trial(start);
+        }
+        let mut scratch = self.scratch.clone();
+        let mut policy = Policy {
+            expected_quality: 0.0,
+            complete: self.is_solved(start),
+            nb_states: self.entries.len(),
+            craft,
+            entries: self.entries,
+            finisher: Mutex::new(self.finisher),
+            expected: HashMap::new(),
+            step_cap: self.step_cap,
+        };
+        let mut expected = HashMap::new();
+        policy.expected_quality = policy.expected_from(start, &mut scratch, &mut expected);
+        policy.expected = expected;
+        policy
+    }
+
+    /// Simulate a craft from the state with the best actions, then check whether the states it went through are solved,
+    /// from the last one to the first one
+    fn trial(&mut self, start: Key) {
+        let actions = self.scratch.action_list();
+        let mut visited = Vec::new();
+        let mut key = start;
+        while !self.is_solved(key) && !self.stopped && visited.len() < MAX_TRIAL_STEPS {
+            visited.push(key);
+            let action = match self.update(key).action {
+                Some(action) => actions.get(action),
+                None => break,
+            };
+            let (state, condition, byregot_used) = key;
+            let child = run(&mut self.scratch, state, condition, action);
+            if child.success != Success::Pending {
+                break;
+            }
+            let next = self.roll(condition);
+            key = self.key(child, next, byregot_used || action.id == ActionId::ByregotBlessing);
+        }
+        while let Some(key) = visited.pop() {
+            if self.stopped || !self.check_solved(key) {
+                break;
+            }
+        }
+    }
+
+    /// Whether the states the best actions can lead to from the state no longer change, they are then solved
+    /// Otherwise the states found on the way are valued again
+    fn check_solved(&mut self, start: Key) -> bool {
+        let actions = self.scratch.action_list();
+        let mut solved = true;
+        let mut open = vec![start];
+        let mut seen = HashSet::from([start]);
+        let mut closed = Vec::new();
+        while let Some(key) = open.pop() {
+            closed.push(key);
+            let best = self.best_action(key);
+            let value = self.estimate(key);
+            // Both are minus infinity for a state the craft can't be finished from
+            if best.value != value && (best.value - value).abs() > RESIDUAL {
+                solved = false;
+                continue;
+            }
+            let action = match best.action {
+                Some(action) => actions.get(action),
+                None => continue,
+            };
+            let (state, condition, byregot_used) = key;
+            let child = run(&mut self.scratch, state, condition, action);
+            if child.success != Success::Pending {
+                continue;
+            }
+            let byregot_used = byregot_used || action.id == ActionId::ByregotBlessing;
+            for &(next, _) in self.scratch.recipe.next_conditions(condition) {
+                let next = self.key(child, next, byregot_used);
+                if !self.is_solved(next) && seen.insert(next) {
+                    open.push(next);
+                }
+            }
+        }
+        if solved {
+            for key in closed {
+                let entry = self.update(key);
+                self.entries.insert(key, Entry { solved: true, ..entry });
+            }
+        } else {
+            while let Some(key) = closed.pop() {
+                self.update(key);
+            }
+        }
+        solved
+    }
+
+    /// Value the state with its best action, a state the craft can't be finished from is solved
+    fn update(&mut self, key: Key) -> Entry {
+        self.nb_updates += 1;
+        if self.nb_updates.is_multiple_of(CHECK_INTERVAL) && self.control.check(CHECK_INTERVAL) {
+            self.stopped = true;
+        }
+        let best = self.best_action(key);
+        let entry = Entry { solved: best.action.is_none(), ..best };
+        self.entries.insert(key, entry);
+        entry
+    }
+
+    /// The actions allowed in a state under a condition
+    fn allowed_actions(&mut self, (state, condition, byregot_used): Key) -> Vec<&'a Action> {
+        self.scratch.set_state(state);
+        self.scratch.condition = condition;
+        let mut allowed = next_action_phases(&self.scratch, byregot_used).into_iter().flatten().collect::<Vec<_>>();
+        // No touch once Byregot's Blessing was used
+        allowed.extend(self.condition_actions.iter().filter(|a| !byregot_used || a.quality == 0));
+        if remaining_progression(&self.scratch) <= 2.0 {
+            let actions = self.scratch.action_list();
+            allowed.extend(self.finisher.finish(state).and_then(|(_, ids)| ids.first()).map(|&id| actions.get(id)));
+        }
+        let mut unique = Vec::with_capacity(allowed.len());
+        for action in allowed {
+            if !unique.contains(&action) {
+                unique.push(action);
+            }
+        }
+        unique
+    }
+
+    /// The action of the state giving the most expected quality for the values known so far
+    fn best_action(&mut self, key: Key) -> Entry {
+        let (state, condition, byregot_used) = key;
+        let mut best = Entry { value: f64::NEG_INFINITY, action: None, solved: false };
+        for action in self.allowed_actions(key) {
+            self.scratch.set_state(state);
+            self.scratch.condition = condition;
+            if !action.can_use(&self.scratch) || action.get_success_rate(&self.scratch) < 100 {
+                continue;
+            }
+            let child = run(&mut self.scratch, state, condition, action);
+            let byregot_used = byregot_used || action.id == ActionId::ByregotBlessing;
+            let future = match child.success {
+                Success::Failure => continue,
+                Success::Success => 0.0,
+                Success::Pending => self.scratch.recipe.next_conditions(condition).iter()
+                    .map(|&(next, chance)| chance * self.estimate(self.key(child, next, byregot_used)))
+                    .sum(),
+            };
+            let value = (child.quality.min(self.target) - state.quality) as f64 - STEP_COST + future;
+            if value > best.value + EPSILON {
+                best = Entry { value, action: Some(action.id), solved: false };
+            }
+        }
+        best
+    }
+
+    /// The expected quality still to gain from a state, from its value when it has one or from the quality bound
+    fn estimate(&self, key: Key) -> f64 {
+        match self.entries.get(&key) {
+            Some(entry) => entry.value,
+            None => self.heuristic(&key.0),
+        }
+    }
+
+    /// The most quality a state could still gain until the target, its conditions being always the best ones
+    fn heuristic(&self, state: &CraftState) -> f64 {
+        let missing_progress = self.scratch.recipe.progress.saturating_sub(state.progression);
+        let max_quality = self.bound.max_quality(state, missing_progress).min(self.target);
+        max_quality.saturating_sub(state.quality) as f64
+    }
+
+    fn is_solved(&self, key: Key) -> bool {
+        self.entries.get(&key).is_some_and(|entry| entry.solved)
+    }
+
+    fn key(&self, state: CraftState, condition: Condition, byregot_used: bool) -> Key {
+        key(state, condition, byregot_used, self.step_cap, self.target)
+    }
+
+    /// The condition of the next step, rolled from the seed
+    fn roll(&mut self, condition: Condition) -> Condition {
+        let next = self.scratch.recipe.next_conditions(condition);
+        let mut roll = self.rng.fraction();
+        for &(condition, chance) in next {
+            if roll < chance {
+                return condition;
+            }
+            roll -= chance;
+        }
+        next[next.len() - 1].0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::craft::fixtures::params;
+    use crate::io::Parameters;
+    use crate::specs::{Recipe, Stats};
+    use super::*;
+
+    /// A crafter without the buffs, Observe or Tricks of the Trade: three synthesis first, as the phases only allow
+    /// the touches from the fourth step, then one touch the cp pays for under the condition of that step, then the
+    /// last synthesis
+    fn craft<'a>() -> Craft<'a> {
+        let mut recipe = Recipe {
+            durability: 50,
+            progress: 100000,
+            quality: 10000,
+            progress_divider: 50,
+            quality_divider: 30,
+            progress_modifier: 100,
+            quality_modifier: 100,
+            expert: false,
+            rlvl: 12,
+            class_job_level: 12,
+        };
+        let stats = Stats { craftsmanship: 100, control: 100, max_cp: 18, specialist: false, level: 12 };
+        let params = Parameters { opener: Opener::Free, ..params() };
+        let mut probe = Craft::new(recipe, stats, params.clone());
+        probe.run_action(&probe.action_list().basic_synthesis, Condition::Normal);
+        recipe.progress = 4 * probe.progression;
+        Craft::new(recipe, stats, params)
+    }
+
+    #[test]
+    fn touches_under_the_condition_rolled() {
+        let craft = craft();
+        let policy = PolicySolver::new(&craft, &SearchControl::default()).solve(craft.clone());
+        assert!(policy.complete);
+
+        let mut scratch = craft.clone();
+        let actions = craft.action_list();
+        for _ in 0..3 {
+            assert_eq!(policy.action(&scratch.state(), Condition::Normal, false), Some(ActionId::BasicSynthesis));
+            scratch.run_action(&actions.basic_synthesis, Condition::Normal);
+        }
+        // The chances of the conditions of the fourth step, the first one being Normal
+        let mut chances = HashMap::from([(Condition::Normal, 1.0)]);
+        for _ in 0..3 {
+            let mut next = HashMap::new();
+            for (condition, chance) in chances {
+                for &(next_condition, next_chance) in craft.recipe.next_conditions(condition) {
+                    *next.entry(next_condition).or_insert(0.0) += chance * next_chance;
+                }
+            }
+            chances = next;
+        }
+        let mut expected = 0.0;
+        for (condition, chance) in chances {
+            assert_eq!(policy.action(&scratch.state(), condition, false), Some(ActionId::BasicTouch), "{:?}", condition);
+            let mut touched = scratch.clone();
+            touched.run_action(&actions.basic_touch, condition);
+            expected += chance * touched.quality as f64;
+        }
+        assert!((policy.expected_quality - expected).abs() < 1e-6, "{} for {}", policy.expected_quality, expected);
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::path::Path;
+use crate::recipe_level::RecipeLevel;
+use crate::specs::Recipe;
+
+/// The jobs in the order of the CraftType sheet
+const JOBS: [&str; 8] = ["CRP", "BSM", "ARM", "GSM", "LTW", "WVR", "ALC", "CUL"];
+
+/// A recipe of the game with what is needed to find it back
+#[derive(Debug, Clone)]
+pub struct RecipeEntry {
+    pub id: u32,
+    pub name: String,
+    pub job: String,
+    pub recipe: Recipe,
+}
+
+/// The recipes read from the Recipe, RecipeLevelTable and Item sheets exported from the game data
+#[derive(Debug, Default)]
+pub struct RecipeDatabase {
+    pub recipes: Vec<RecipeEntry>,
+    /// The rows of the RecipeLevelTable sheet, by recipe level
+    pub levels: Vec<RecipeLevel>,
+}
+
+/// A csv export of a game sheet, the first line holds the column indexes,
+/// the second one the column names and the third one their types
+struct Sheet {
+    file_name: String,
+    columns: HashMap<String, usize>,
+    rows: Vec<csv::StringRecord>,
+}
+
+impl Sheet {
+    fn read(directory: &Path, name: &str) -> Result<Self, String> {
+        let path = directory.join(format!("{}.csv", name));
+        let file_name = path.display().to_string();
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(&path)
+            .map_err(|e| format!("Can't open '{}': {}", file_name, e))?;
+        let mut records = reader.records()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Can't read '{}': {}", file_name, e))?;
+        if records.len() < 3 {
+            return Err(format!("'{}' is missing its header lines", file_name));
+        }
+        let rows = records.split_off(3);
+        let columns = records[1].iter().enumerate()
+            .map(|(i, column)| (column.to_string(), i))
+            .collect();
+        Ok(Self { file_name, columns, rows })
+    }
+
+    fn column(&self, name: &str) -> Result<usize, String> {
+        self.columns.get(name).copied()
+            .ok_or(format!("Can't find the column '{}' in '{}'", name, self.file_name))
+    }
+
+    fn value<'s>(&self, row: &'s csv::StringRecord, column: usize) -> &'s str {
+        row.get(column).unwrap_or("")
+    }
+
+    fn int(&self, row: &csv::StringRecord, column: usize) -> Result<u32, String> {
+        let value = self.value(row, column);
+        value.parse().map_err(|_| format!("Can't convert '{}' as an integer in '{}'", value, self.file_name))
+    }
+}
+
+impl RecipeDatabase {
+    /// Import the Recipe.csv, RecipeLevelTable.csv and Item.csv exports of a directory
+    pub fn from_directory(directory: &str) -> Result<Self, String> {
+        let directory = Path::new(directory);
+
+        let items = Sheet::read(directory, "Item")?;
+        let (item_id, item_name) = (items.column("#")?, items.column("Name")?);
+        let mut names = HashMap::new();
+        for row in &items.rows {
+            names.insert(items.int(row, item_id)?, items.value(row, item_name).to_string());
+        }
+
+        let table = Sheet::read(directory, "RecipeLevelTable")?;
+        let columns = ["#", "ClassJobLevel", "Stars", "ProgressDivider", "QualityDivider",
+            "ProgressModifier", "QualityModifier", "Difficulty", "Quality", "Durability"]
+            .iter().map(|c| table.column(c)).collect::<Result<Vec<_>, _>>()?;
+        let mut levels = HashMap::new();
+        for row in &table.rows {
+            let v = columns.iter().map(|&c| table.int(row, c)).collect::<Result<Vec<_>, _>>()?;
+            let level = RecipeLevel {
+                rlvl: v[0],
+                class_job_level: v[1],
+                stars: v[2],
+                progress_divider: v[3],
+                quality_divider: v[4],
+                progress_modifier: v[5],
+                quality_modifier: v[6],
+            };
+            // Keep the base difficulty, quality and durability, the recipes only give a factor of them
+            levels.insert(level.rlvl, (level, v[7], v[8], v[9]));
+        }
+
+        let sheet = Sheet::read(directory, "Recipe")?;
+        let (id, job, rlvl, result) = (sheet.column("#")?, sheet.column("CraftType")?,
+            sheet.column("RecipeLevelTable")?, sheet.column("Item{Result}")?);
+        let (difficulty_factor, quality_factor, durability_factor) = (sheet.column("DifficultyFactor")?,
+            sheet.column("QualityFactor")?, sheet.column("DurabilityFactor")?);
+        let expert = sheet.column("IsExpert")?;
+
+        let mut recipes = Vec::new();
+        for row in &sheet.rows {
+            let name = match names.get(&sheet.int(row, result)?) {
+                Some(name) if !name.is_empty() => name.clone(),
+                _ => continue,
+            };
+            let (level, difficulty, quality, durability) = match levels.get(&sheet.int(row, rlvl)?) {
+                Some(level) => *level,
+                None => continue,
+            };
+            recipes.push(RecipeEntry {
+                id: sheet.int(row, id)?,
+                name,
+                job: JOBS.get(sheet.int(row, job)? as usize).unwrap_or(&"???").to_string(),
+                recipe: Recipe {
+                    durability: durability * sheet.int(row, durability_factor)? / 100,
+                    progress: difficulty * sheet.int(row, difficulty_factor)? / 100,
+                    quality: quality * sheet.int(row, quality_factor)? / 100,
+                    progress_divider: level.progress_divider,
+                    quality_divider: level.quality_divider,
+                    progress_modifier: level.progress_modifier,
+                    quality_modifier: level.quality_modifier,
+                    expert: sheet.value(row, expert).eq_ignore_ascii_case("true"),
+                    rlvl: level.rlvl,
+                    class_job_level: level.class_job_level,
+                },
+            });
+        }
+        let mut levels = levels.into_values().map(|(level, _, _, _)| level).collect::<Vec<_>>();
+        levels.sort_by_key(|level| level.rlvl);
+        Ok(Self { recipes, levels })
+    }
+
+    /// Find the recipes whose name looks like the text, the closest first
+    pub fn search(&self, text: &str) -> Vec<&RecipeEntry> {
+        let text = text.to_lowercase();
+        let mut found = self.recipes.iter()
+            .map(|entry| (name_score(&text, &entry.name.to_lowercase()), entry))
+            .filter(|(score, _)| *score >= 0.8)
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.name.cmp(&b.1.name)));
+        found.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    /// Find a recipe by its name, or the closest one if none has that exact name
+    pub fn find(&self, name: &str) -> Option<&RecipeEntry> {
+        self.recipes.iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+            .or_else(|| self.search(name).into_iter().next())
+    }
+}
+
+/// How close a name is from the searched text, from 0 to 2
+fn name_score(text: &str, name: &str) -> f64 {
+    if name == text {
+        return 2.0;
+    }
+    // Names containing the text come first, the shortest ones being the closest
+    if name.contains(text) {
+        return 1.0 + text.len() as f64 / name.len() as f64;
+    }
+    // Otherwise allow for typos, on the whole name or on as many of its words as the text has
+    let words = name.split_whitespace().collect::<Vec<_>>();
+    let text_words = text.split_whitespace().count().max(1);
+    let mut score = strsim::jaro_winkler(text, name);
+    for window in words.windows(text_words.min(words.len()).max(1)) {
+        score = score.max(strsim::jaro_winkler(text, &window.join(" ")));
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> RecipeDatabase {
+        RecipeDatabase::from_directory(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/recipe_db")).unwrap()
+    }
+
+    #[test]
+    fn imports_the_recipes() {
+        let db = database();
+        assert_eq!(db.recipes.len(), 3);
+        let entry = db.find("Grade 8 Tincture of Strength").unwrap();
+        assert_eq!((entry.id, entry.job.as_str()), (10, "ALC"));
+        assert_eq!((entry.recipe.progress, entry.recipe.quality, entry.recipe.durability), (5060, 12628, 70));
+        assert_eq!((entry.recipe.progress_divider, entry.recipe.quality_modifier), (130, 70));
+        assert!(!entry.recipe.expert);
+
+        let entry = db.find("Rinascita Ingot").unwrap();
+        assert_eq!((entry.job.as_str(), entry.recipe.expert), ("BSM", true));
+        assert_eq!((entry.recipe.progress, entry.recipe.quality, entry.recipe.durability), (2530, 10102, 35));
+        assert_eq!(db.find("Item with \"quotes\", and\nnewline").unwrap().id, 12);
+    }
+
+    #[test]
+    fn finds_close_names() {
+        let db = database();
+        assert_eq!(db.find("tincture of strength").unwrap().id, 10);
+        assert_eq!(db.find("Rinascta Ingot").unwrap().id, 11);
+        assert!(db.search("nothing like it").is_empty());
+    }
+
+    #[test]
+    fn keeps_the_level_table() {
+        let levels = database().levels;
+        assert_eq!(levels.iter().map(|level| level.rlvl).collect::<Vec<_>>(), [1, 560, 640]);
+        assert_eq!(levels[0].to_csv_line(), "1,1,0,50,30,100,100");
+        assert_eq!(levels[1].to_csv_line(), crate::recipe_level::find(560).unwrap().to_csv_line());
+    }
+
+    #[test]
+    fn reports_missing_sheets() {
+        let error = RecipeDatabase::from_directory("/nonexistent").unwrap_err();
+        assert!(error.starts_with("Can't open"), "{}", error);
+    }
+}
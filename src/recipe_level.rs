@@ -0,0 +1,82 @@
+use lazy_static::lazy_static;
+
+/// The values the game derives from the level of a recipe
+#[derive(Debug, Clone, Copy)]
+pub struct RecipeLevel {
+    pub rlvl: u32,
+    pub class_job_level: u32,
+    pub stars: u32,
+    pub progress_divider: u32,
+    pub quality_divider: u32,
+    pub progress_modifier: u32,
+    pub quality_modifier: u32,
+}
+
+/// The header of the embedded csv
+pub const CSV_HEADER: &str = "rlvl,class_job_level,stars,progress_divider,quality_divider,progress_modifier,quality_modifier";
+
+impl RecipeLevel {
+    /// Read a line of the table, in the column order of the embedded csv
+    pub fn from_csv_line(line: &str) -> Option<Self> {
+        let values = line.split(',').map(|v| v.trim().parse::<u32>().ok()).collect::<Option<Vec<u32>>>()?;
+        match values[..] {
+            [rlvl, class_job_level, stars, progress_divider, quality_divider, progress_modifier, quality_modifier] => Some(Self {
+                rlvl,
+                class_job_level,
+                stars,
+                progress_divider,
+                quality_divider,
+                progress_modifier,
+                quality_modifier,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Write the level as a line of the embedded csv
+    pub fn to_csv_line(&self) -> String {
+        format!("{},{},{},{},{},{},{}", self.rlvl, self.class_job_level, self.stars,
+            self.progress_divider, self.quality_divider, self.progress_modifier, self.quality_modifier)
+    }
+}
+
+lazy_static! {
+/// The recipe levels known without any game data,
+/// the table can be written again from a RecipeLevelTable export with `recipes levels`
+pub static ref RECIPE_LEVELS: Vec<RecipeLevel> = include_str!("../data/recipe_level_table.csv")
+    .lines()
+    .skip(1)
+    .filter_map(RecipeLevel::from_csv_line)
+    .collect();
+}
+
+/// Find the built-in values of a recipe level
+pub fn find(rlvl: u32) -> Option<RecipeLevel> {
+    RECIPE_LEVELS.iter().find(|level| level.rlvl == rlvl).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_its_lines() {
+        for level in RECIPE_LEVELS.iter() {
+            let line = level.to_csv_line();
+            assert_eq!(RecipeLevel::from_csv_line(&line).unwrap().to_csv_line(), line);
+        }
+        assert_eq!(include_str!("../data/recipe_level_table.csv").lines().next(), Some(CSV_HEADER));
+        assert!(RecipeLevel::from_csv_line(CSV_HEADER).is_none());
+        assert!(RecipeLevel::from_csv_line("560,90,0").is_none());
+    }
+
+    #[test]
+    fn finds_endgame_levels() {
+        let level = find(560).unwrap();
+        assert_eq!((level.class_job_level, level.progress_divider, level.quality_divider), (90, 130, 115));
+        assert_eq!((level.progress_modifier, level.quality_modifier), (90, 80));
+        let level = find(640).unwrap();
+        assert_eq!((level.stars, level.progress_modifier, level.quality_modifier), (4, 80, 70));
+        assert!(find(0).is_none());
+    }
+}
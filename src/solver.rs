@@ -1,42 +1,64 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ops::Not;
-use crate::action::{Action, ACTIONS};
-use crate::craft::Craft;
-use crate::specs::Success;
+use std::sync::Mutex;
+use crate::action::{Action, ActionId};
+use crate::bound::QualityBound;
+use crate::control::{SearchControl, CHECK_INTERVAL};
+use crate::craft::{Craft, CraftState};
+use crate::exact;
+use crate::io::Opener;
+use crate::node::NodeArena;
+use crate::specs::{BuffState, Condition, Success};
 
 macro_rules! action_vec {
     ($($tt:expr),*) => { vec![ $(Some(&$tt),)*]};
 }
 
+/// Steps the opener buffs are searched over when the opener isn't given
+const OPENER_STEPS: u32 = 3;
+
+/// The first step actions and buffs an opener is made of, each buff once
+fn opener_actions<'a>(craft: &Craft<'a>) -> Vec<Option<&'a Action>> {
+    let actions = craft.action_list();
+    let mut opener_actions = Vec::new();
+    if craft.step_count == 0 { opener_actions.append(&mut action_vec![actions.muscle_memory, actions.reflect]); }
+    if craft.buffs.manipulation == 0 { opener_actions.append(&mut action_vec![actions.manipulation]); }
+    if craft.buffs.veneration == 0 { opener_actions.append(&mut action_vec![actions.veneration]); }
+    if craft.buffs.waste_not == 0 { opener_actions.append(&mut action_vec![actions.waste_not, actions.waste_not_ii]); }
+    opener_actions
+}
+
 /// Find the next authorised action for this step of the craft
 pub fn next_action_picker_1<'a>(craft: & Craft<'a>) -> Vec<Option<&'a Action>> {
     if craft.success != Success::Pending { return vec![None]; }
+    let actions = craft.action_list();
     let mut available_actions = Vec::<Option<&'a Action>>::new();
     let mut forbidden_actions = Vec::<Option<&'a Action>>::new();
 
-    // Optimize the first three steps for massive time save
-    if craft.step_count == 0 { return action_vec![ACTIONS.muscle_memory]; }
-    if craft.step_count == 1 { return action_vec![ACTIONS.manipulation]; }
+    // The opener is given for massive time save, or searched over its first steps
+    if let Some(allowed) = craft.args.opener.allowed_actions(craft.step_count) {
+        return allowed.iter().map(|&id| actions.get(id)).filter(|a| a.can_use(craft)).map(Some).collect();
+    }
+    if craft.step_count < OPENER_STEPS { available_actions.append(&mut opener_actions(craft)); }
 
     // Prune some actions if not requested by --long
-    if craft.step_count == 2 { return action_vec![/*ACTIONS.waste_not, ACTIONS.waste_not_ii, */ACTIONS.veneration]; }
-    if craft.step_count == 3 { available_actions.append(&mut action_vec![ACTIONS.waste_not_ii/*,ACTIONS.waste_not*/]) }
+    if craft.step_count == 3 { available_actions.append(&mut action_vec![actions.waste_not_ii/*,actions.waste_not*/]) }
 
     // Groundwork mostly for wn / mm
-    if craft.buffs.waste_not > 0 || craft.buffs.muscle_memory > 0 { available_actions.append(&mut action_vec![ACTIONS.groundwork]) }
+    if craft.buffs.waste_not > 0 || craft.buffs.muscle_memory > 0 { available_actions.append(&mut action_vec![actions.groundwork]) }
     
     // Forbidding actions depending on buffs
-    if craft.buffs.muscle_memory > 0 { forbidden_actions.append(&mut action_vec![ACTIONS.basic_synthesis,ACTIONS.careful_synthesis,ACTIONS.prudent_synthesis,ACTIONS.delicate_synthesis]) }
-    if craft.buffs.waste_not > 0 { forbidden_actions.append(&mut action_vec![ACTIONS.prudent_synthesis]) }
-    available_actions.append(&mut action_vec![ACTIONS.basic_synthesis,ACTIONS.careful_synthesis,ACTIONS.prudent_synthesis,ACTIONS.delicate_synthesis]);
+    if craft.buffs.muscle_memory > 0 { forbidden_actions.append(&mut action_vec![actions.basic_synthesis,actions.careful_synthesis,actions.prudent_synthesis,actions.delicate_synthesis]) }
+    if craft.buffs.waste_not > 0 { forbidden_actions.append(&mut action_vec![actions.prudent_synthesis]) }
+    available_actions.append(&mut action_vec![actions.basic_synthesis,actions.careful_synthesis,actions.prudent_synthesis,actions.delicate_synthesis]);
     
-    // ACTIONS.groundwork for the --pls
+    // actions.groundwork for the --pls
     if craft.args.desperate{
-        available_actions.append(&mut action_vec![ACTIONS.groundwork]);
+        available_actions.append(&mut action_vec![actions.groundwork]);
     }
 
     // For long first run
-    if craft.step_count > 8 {available_actions.append(&mut action_vec![ACTIONS.waste_not_ii, ACTIONS.waste_not])};
+    if craft.step_count > 8 {available_actions.append(&mut action_vec![actions.waste_not_ii, actions.waste_not])};
 
     // Pruning the actions with the forbidden ones
     let mut result_actions = Vec::<Option<&'a Action>>::new();
@@ -50,34 +72,41 @@ pub fn next_action_picker_1<'a>(craft: & Craft<'a>) -> Vec<Option<&'a Action>> {
 }
 
 
-/// Find all routes that can finish in one more action
-pub fn generate_routes_phase1<'a>(craft: Craft<'a>) -> Vec<Craft<'a>> {
-    let mut queue = Vec::new();
-    queue.push(craft);
+/// Find all routes that can finish in one more action, along with the crafts reaching the split step
+/// The crafts reaching the split step are not searched further, so they can be searched apart by other threads
+/// The crafts searched are kept as nodes of an arena, only the routes found get their actions back
+pub fn generate_routes_phase1<'a>(craft: Craft<'a>, split_step: Option<u32>, control: &SearchControl) -> (Vec<Craft<'a>>, Vec<Craft<'a>>) {
+    if control.is_stopped() {
+        return (Vec::new(), Vec::new());
+    }
+    let mut arena = NodeArena::new();
+    let mut scratch = craft.clone();
+    let mut finisher = Finisher::new(&craft);
+    let mut queue = vec![arena.root(craft.state())];
     let mut routes = Vec::new();
-    while !queue.is_empty() {
-        let craft = queue.pop().unwrap();
-        for action in next_action_picker_1(& craft) {
-            let mut craft = craft.clone();
-
-            match action{
-                Some(a) => craft.run_action(a),
+    let mut split = Vec::new();
+    let mut nb_nodes: usize = 0;
+    while let Some(node) = queue.pop() {
+        nb_nodes += 1;
+        if nb_nodes.is_multiple_of(CHECK_INTERVAL) && control.check(CHECK_INTERVAL) {
+            break;
+        }
+        arena.truncate(node);
+        arena.load(&mut scratch, node);
+        for action in next_action_picker_1(&scratch) {
+            let action = match action {
+                Some(a) => a,
                 None => break,
             };
+            arena.load(&mut scratch, node);
+            scratch.run_action(action, Condition::Normal);
 
-            let remaining_prog = (craft.recipe.progress as f32 - craft.progression as f32) / craft.get_base_progression() as f32;
+            let remaining_prog = remaining_progression(&scratch);
             if remaining_prog <= 2.0 {
-                if remaining_prog <= 0.0 {
-                    continue;
-                } else if 0.0 < remaining_prog && remaining_prog <= 1.2 {
-                    // pass         // Will finish with basicSynth2 (free)
-                } else if 1.2 < remaining_prog && remaining_prog <= 1.2 {
-                    craft.cp -= 7; // Will finish with carefulSynthesis (7cp)
-                } else if 1.2 < remaining_prog && remaining_prog <= 2.0 {
-                    craft.cp -= 12; // Will finish with observe+focusedSynthesis (12cp)
+                // The last synthesis is run after the touches, the route is only kept when there is one
+                if remaining_prog > 0.0 && finisher.finish(scratch.state()).is_some() {
+                    routes.push(arena.route(&craft, node, &scratch));
                 }
-                craft.durability -= 10; // Save the final step, since we always aim to end with -5dur, it doesn't matter
-                routes.push(craft);
                 continue;
             }
 
@@ -85,60 +114,68 @@ pub fn generate_routes_phase1<'a>(craft: Craft<'a>) -> Vec<Craft<'a>> {
             // 8 Seemed to be a good initial guess with enouth to use a good amount of WN / WNII to get close
             // A higher value will yield more initial guesses with minimal benefits
             // A lover value will yield less initial guesses and might spent too much CP to get to the required progression
-            if craft.step_count < craft.args.depth { queue.push(craft); }
+            if scratch.step_count >= scratch.args.depth { continue; }
+            if split_step.is_some_and(|step| scratch.step_count >= step) {
+                split.push(arena.route(&craft, node, &scratch));
+            } else {
+                queue.push(arena.push(scratch.state(), node, action.id));
+            }
         }
     }
-    routes
+    (routes, split)
 }
 
 
 /// Find the list of authorised actions for this step
 pub fn next_action_phase_2<'a>(craft: & Craft<'a>) -> Vec<Option<&'a Action>> {
-    let mut available_actions = vec![&ACTIONS.basic_touch, &ACTIONS.prudent_touch, &ACTIONS.preparatory_touch];
+    let actions = craft.action_list();
+    let mut available_actions = vec![&actions.basic_touch, &actions.prudent_touch, &actions.preparatory_touch];
     let mut forbidden_actions = Vec::new();
     if craft.success != Success::Pending { return vec![None]; }
     if craft.buffs.innovation > 0 {
-        forbidden_actions.push(&ACTIONS.innovation);
+        forbidden_actions.push(&actions.innovation);
     } else {
         if craft.buffs.inner_quiet >= 2 {
-            forbidden_actions.append(&mut vec![&ACTIONS.basic_touch,
-                                               &ACTIONS.standard_touch,
-                                               &ACTIONS.advanced_touch,
-                                               &ACTIONS.trained_finesse,
-                                               &ACTIONS.prudent_touch,
-                                               &ACTIONS.preparatory_touch,
-                                               &ACTIONS.byregot_blessing]);
+            forbidden_actions.append(&mut vec![&actions.basic_touch,
+                                               &actions.standard_touch,
+                                               &actions.advanced_touch,
+                                               &actions.trained_finesse,
+                                               &actions.prudent_touch,
+                                               &actions.preparatory_touch,
+                                               &actions.byregot_blessing]);
         }
-        available_actions.push(&ACTIONS.innovation);
+        available_actions.push(&actions.innovation);
     }
     if craft.buffs.manipulation == 0 && craft.buffs.basic_touch == 0 && craft.buffs.standard_touch == 0 && craft.buffs.inner_quiet < 8 {
-        available_actions.push(&ACTIONS.manipulation);
+        available_actions.push(&actions.manipulation);
     }
     if craft.buffs.waste_not > 0 {
-        available_actions.push(&ACTIONS.preparatory_touch);
-        forbidden_actions.push(&ACTIONS.prudent_touch);
+        available_actions.push(&actions.preparatory_touch);
+        forbidden_actions.push(&actions.prudent_touch);
     } else {
-        available_actions.push(&ACTIONS.prudent_touch);
-        forbidden_actions.push(&ACTIONS.preparatory_touch);
+        available_actions.push(&actions.prudent_touch);
+        forbidden_actions.push(&actions.preparatory_touch);
     }
     if craft.buffs.basic_touch > 0 {
-        available_actions.push(&ACTIONS.standard_touch);
-        forbidden_actions.push(&ACTIONS.basic_touch);
+        available_actions.push(&actions.standard_touch);
+        // Refined Touch is only worth it for its additional Inner Quiet stack
+        if craft.buffs.inner_quiet < 9 { available_actions.push(&actions.refined_touch); }
+        forbidden_actions.push(&actions.basic_touch);
     }
     if craft.buffs.standard_touch > 0 {
-        available_actions.push(&ACTIONS.advanced_touch);
-        forbidden_actions.push(&ACTIONS.basic_touch);
+        available_actions.push(&actions.advanced_touch);
+        forbidden_actions.push(&actions.basic_touch);
     }
     // Todo, allow earlier byregot if the craft can be finished all the same (useless with always optimize on)
     if craft.buffs.inner_quiet >= craft.args.byregot_step { // 10
-        available_actions.push(&ACTIONS.trained_finesse);
-        available_actions.push(&ACTIONS.great_strides);
+        available_actions.push(&actions.trained_finesse);
+        available_actions.push(&actions.great_strides);
     }
     if craft.buffs.great_strides > 0 {
-        forbidden_actions.push(&ACTIONS.trained_finesse);
-        forbidden_actions.push(&ACTIONS.great_strides);
+        forbidden_actions.push(&actions.trained_finesse);
+        forbidden_actions.push(&actions.great_strides);
         if craft.buffs.innovation > 0 {
-            available_actions.push(&ACTIONS.byregot_blessing);
+            available_actions.push(&actions.byregot_blessing);
         }
     }
     let mut final_actions: Vec<Option<&Action>> = Vec::new();
@@ -151,40 +188,496 @@ pub fn next_action_phase_2<'a>(craft: & Craft<'a>) -> Vec<Option<&'a Action>> {
     final_actions
 }
 
+/// The best finished route of the searches running in parallel, shared so that each one prunes against the others
+#[derive(Debug, Default)]
+pub struct SharedBest(Mutex<Option<CraftState>>);
+
+impl SharedBest {
+    pub fn get(&self) -> Option<CraftState> {
+        *self.0.lock().unwrap()
+    }
+
+    /// Keep a finished route if it is better than the best one
+    pub fn offer(&self, craft: &Craft) {
+        let mut best = self.0.lock().unwrap();
+        let is_better = match (craft.args.objective, *best) {
+            (_, None) => true,
+            (Some(objective), Some(best)) => objective.key(&craft.state(), craft.recipe.quality) > objective.key(&best, craft.recipe.quality),
+            (None, Some(best)) => craft.quality > best.quality,
+        };
+        if is_better { *best = Some(craft.state()); }
+    }
+}
+
 /// Apply all actions to the current route
-pub fn generate_routes_phase2<'a>(craft: Craft<'a>) -> Option<Vec<Craft<'a>>> {
-    let mut queue = VecDeque::new();
+/// Routes whose quality bound can neither reach the recipe quality nor beat the best route are dropped,
+/// as well as the ones that can't beat the best route of the other searches for the objective, or can't reach
+/// the recipe quality once another search did without one
+/// When stopped, the best route found so far is kept
+/// The crafts searched are kept as nodes of an arena, only the finished routes get their actions back
+pub fn generate_routes_phase2<'a>(craft: Craft<'a>, bound: &QualityBound, shared: &SharedBest, control: &SearchControl) -> Option<Vec<Craft<'a>>> {
+    // Routes not started before the stop are left out
+    if control.is_stopped() {
+        return None;
+    }
+    let actions = craft.action_list();
+    let mut arena = NodeArena::new();
+    let mut scratch = craft.clone();
+    let mut finisher = Finisher::new(&craft);
     
     // Todo: Return a vec of relevant functions, instead of the most quality one
-    let mut top_route: Craft<'a> = craft.clone(); // Default route, no hq on that one
+    let mut top_route: Craft<'a> = finisher.finish_craft(&craft)?; // Default route, no hq on that one
+    control.found_route(&top_route);
+    shared.offer(&top_route);
     let mut top_routes: Vec<Craft<'a>> = vec![];
+    // Refreshed along the checks of the control, the lock isn't taken for each craft
+    let mut shared_best = shared.get();
 
-    queue.push_back(craft);
+    let mut queue = VecDeque::from([arena.root(craft.state())]);
+    let mut nb_nodes: usize = 0;
 
-    while !queue.is_empty() {
-        let _craft = queue.pop_front().unwrap();
-        for action in next_action_phase_2(&_craft) {
-            if _craft.success != Success::Pending || action.is_none() || !action.unwrap().can_use(&_craft) {
+    while let Some(node) = queue.pop_front() {
+        nb_nodes += 1;
+        if nb_nodes.is_multiple_of(CHECK_INTERVAL) {
+            if control.check(CHECK_INTERVAL) {
+                break;
+            }
+            shared_best = shared.get();
+        }
+        arena.load(&mut scratch, node);
+        for action in next_action_phase_2(&scratch) {
+            arena.load(&mut scratch, node);
+            if scratch.success != Success::Pending || action.is_none() || !action.unwrap().can_use(&scratch) {
                 continue;
             }
-            let mut craft = _craft.clone();
-            craft.run_action(action.unwrap());
-            if action.unwrap() == &ACTIONS.byregot_blessing {
+            let action = action.unwrap();
+            scratch.run_action(action, Condition::Normal);
+            if is_over_steps(&scratch) {
+                continue;
+            }
+            if action == &actions.byregot_blessing {
+                let Some(finished) = finisher.finish_craft(&scratch) else { continue };
+                let craft = arena.route(&craft, node, &finished);
                 #[cfg(not(feature="fast"))]
                 if top_route.quality>=craft.recipe.quality{
                     top_routes.push(craft.clone());  // Me memory
                 }
-                if top_route.quality < craft.quality {
+                if is_better(&craft, &top_route) {
                     #[cfg(feature="fast")]
                     top_routes.push(craft.clone());
+                    control.found_route(&craft);
+                    shared.offer(&craft);
                     top_route = craft;
                 }
             } else {
-                queue.push_back(craft);
+                let max_quality = bound.max_quality(&scratch.state(), scratch.recipe.progress.saturating_sub(scratch.progression));
+                let target = scratch.recipe.quality;
+                let can_beat = match scratch.args.objective {
+                    Some(objective) => objective.can_beat(max_quality, &scratch, &top_route.state())
+                        && shared_best.is_none_or(|best| objective.can_beat(max_quality, &scratch, &best)),
+                    None => max_quality >= target
+                        || (max_quality > top_route.quality && shared_best.is_none_or(|best| best.quality < target)),
+                };
+                if !can_beat {
+                    continue;
+                }
+                queue.push_back(arena.push(scratch.state(), node, action.id));
             }
         }
     }
     // Let's not forget the best result
     top_routes.push(top_route);
     Some(top_routes)
-}
\ No newline at end of file
+}
+
+/// Keep a route reaching the recipe quality unless another one is as short with as much durability and cp left,
+/// the ones it beats are dropped so only a few routes are kept however many finish the craft
+fn keep_hq_route<'a>(routes: &mut Vec<Craft<'a>>, route: &Craft<'a>) {
+    let beats = |a: &Craft, b: &Craft| a.step_count <= b.step_count && a.durability >= b.durability && a.cp >= b.cp;
+    if routes.iter().any(|kept| beats(kept, route)) {
+        return;
+    }
+    routes.retain(|kept| !beats(route, kept));
+    routes.push(route.clone());
+}
+
+/// Whether a finished route is better than another for the objective, or with more quality without one
+fn is_better(craft: &Craft, other: &Craft) -> bool {
+    match craft.args.objective {
+        Some(objective) => objective.key(&craft.state(), craft.recipe.quality) > objective.key(&other.state(), other.recipe.quality),
+        None => craft.quality > other.quality,
+    }
+}
+
+/// Whether a route can't end within the steps of the objective, finishing it takes one more step at least
+fn is_over_steps(craft: &Craft) -> bool {
+    craft.args.objective.and_then(|o| o.max_steps()).is_some_and(|max_steps| craft.step_count >= max_steps)
+}
+
+/// Base progressions still missing, the synthesis stop within two of them and the last ones come after the touches
+pub(crate) fn remaining_progression(craft: &Craft) -> f32 {
+    (craft.recipe.progress as f32 - craft.progression as f32) / craft.get_base_progression() as f32
+}
+
+/// Most steps of the finisher, like Observe then Focused Synthesis
+const FINISHER_STEPS: u32 = 2;
+
+/// Finishes the crafts with the synthesis that can't fail, in the least steps then with the most cp and durability left
+/// The finishers are simulated on a scratch craft from the state, and the ones found are kept for the states reached again
+pub struct Finisher<'a> {
+    /// Craft used to simulate the actions, its actions are cleared before each one
+    scratch: Craft<'a>,
+    actions: Vec<&'a Action>,
+    /// The actions leading to the state being searched
+    path: Vec<ActionId>,
+    /// The state each state ends in and the actions finishing it, none when it can't be finished
+    found: HashMap<CraftState, Option<(CraftState, Vec<ActionId>)>>,
+}
+
+impl<'a> Finisher<'a> {
+    pub fn new(craft: &Craft<'a>) -> Self {
+        let mut scratch = craft.clone();
+        scratch.actions.clear();
+        scratch.condition = Condition::Normal;
+        Self {
+            actions: craft.action_list().iter()
+                .filter(|a| a.progress > 0 || a.id == ActionId::Observe || a.id == ActionId::Veneration)
+                .collect(),
+            scratch,
+            path: Vec::new(),
+            found: HashMap::new(),
+        }
+    }
+
+    /// The state a craft ends in and the actions finishing it, from the state it is in
+    pub fn finish(&mut self, state: CraftState) -> Option<&(CraftState, Vec<ActionId>)> {
+        if !self.found.contains_key(&state) {
+            let mut best = None;
+            self.search(state, state.step_count + FINISHER_STEPS, &mut best);
+            self.found.insert(state, best);
+        }
+        self.found[&state].as_ref()
+    }
+
+    fn search(&mut self, state: CraftState, last_step: u32, best: &mut Option<(CraftState, Vec<ActionId>)>) {
+        for i in 0..self.actions.len() {
+            let action = self.actions[i];
+            self.scratch.set_state(state);
+            if !action.can_use(&self.scratch) || action.get_success_rate(&self.scratch) < 100 {
+                continue;
+            }
+            self.scratch.actions.clear();
+            self.scratch.run_action(action, Condition::Normal);
+            let child = self.scratch.state();
+            self.path.push(action.id);
+            match child.success {
+                Success::Success => {
+                    let key = |s: &CraftState| (std::cmp::Reverse(s.step_count), s.cp, s.durability);
+                    if best.as_ref().is_none_or(|(b, _)| key(&child) > key(b)) {
+                        *best = Some((child, self.path.clone()));
+                    }
+                },
+                Success::Pending if child.step_count < last_step => self.search(child, last_step, best),
+                _ => {},
+            }
+            self.path.pop();
+        }
+    }
+
+    /// Finish the craft, the actions are run on a copy of it so it ends as the game shows it
+    pub fn finish_craft(&mut self, craft: &Craft<'a>) -> Option<Craft<'a>> {
+        let (state, ids) = self.finish(craft.state())?;
+        let mut route = craft.clone();
+        route.set_state(*state);
+        route.condition = Condition::Normal;
+        let actions = route.action_list();
+        route.actions.extend(ids.iter().map(|&id| actions.get(id)));
+        Some(route)
+    }
+}
+
+/// Find the actions of both phases at every step, for the searches that can't afford all of them
+pub fn next_action_phases<'a>(craft: &Craft<'a>, byregot_used: bool) -> Vec<Option<&'a Action>> {
+    if craft.success != Success::Pending { return vec![None]; }
+    let actions = craft.action_list();
+    // Same opener as the first phase
+    if craft.step_count < OPENER_STEPS { return next_action_picker_1(craft); }
+
+    let mut available_actions = Vec::new();
+    if remaining_progression(craft) > 2.0 {
+        available_actions.append(&mut next_action_picker_1(craft));
+        // Veneration again for the synthesis after some touches
+        if craft.buffs.veneration == 0 { available_actions.push(Some(&actions.veneration)); }
+    }
+    // No more touches once Byregot's Blessing spent the inner quiet
+    if !byregot_used {
+        for action in next_action_phase_2(craft) {
+            if !available_actions.contains(&action) { available_actions.push(action); }
+        }
+    }
+    available_actions
+}
+
+/// Find the authorised actions of the unified search, every action of the crafter that can be used and can't fail,
+/// only the ones of the opener when it is given
+pub fn next_action_unified<'a>(craft: &Craft<'a>) -> Vec<Option<&'a Action>> {
+    if craft.success != Success::Pending { return vec![None]; }
+    // The classic opener is left to the phases, like the exact search does
+    let opener = match &craft.args.opener {
+        Opener::Classic => None,
+        opener => opener.allowed_actions(craft.step_count),
+    };
+    craft.action_list().iter()
+        // Careful Observation is left out since the condition is always Normal
+        .filter(|a| a.id != ActionId::CarefulObservation)
+        .filter(|a| craft.args.game_version.has_action(a.id) && craft.stats.level >= a.level)
+        .filter(|a| opener.is_none_or(|allowed| allowed.contains(&a.id)))
+        .filter(|a| a.can_use(craft) && a.get_success_rate(craft) >= 100)
+        .map(Some)
+        .collect()
+}
+
+/// Search the progression and the touches together with every action, a route is done when its synthesis finishes
+/// the craft, so the touches can come in any order with the synthesis and Byregot's Blessing is only one of them
+/// Routes whose quality bound can't beat the best route are dropped, as well as the ones reaching
+/// a progression and buffs already reached with more quality, cp and durability
+/// When stopped, the best route found so far is kept, or the craft still to search with the most quality
+/// finished by the last synthesis when none was found yet
+/// The crafts searched are kept as nodes of an arena, only the finished routes get their actions back
+pub fn generate_routes_unified<'a>(craft: Craft<'a>, bound: &QualityBound, control: &SearchControl) -> (Vec<Craft<'a>>, usize) {
+    let mut arena = NodeArena::new();
+    let mut scratch = craft.clone();
+    let mut finisher = Finisher::new(&craft);
+    let mut queue = vec![arena.root(craft.state())];
+    let mut reached = HashMap::<(u32, BuffState), Vec<(u32, i32, i32, u32)>>::new();
+    let mut top_route: Option<Craft<'a>> = None;
+    let mut top_routes: Vec<Craft<'a>> = vec![];
+    let mut nb_states: usize = 0;
+
+    while let Some(node) = queue.pop() {
+        nb_states += 1;
+        if nb_states.is_multiple_of(CHECK_INTERVAL) && control.check(CHECK_INTERVAL) {
+            break;
+        }
+        arena.truncate(node);
+        let mut children = Vec::new();
+        arena.load(&mut scratch, node);
+        // The actions of the phases are searched first, so good routes are found early to prune the others
+        let preferred = next_action_phases(&scratch, false);
+        for action in next_action_unified(&scratch) {
+            let Some(action) = action else { break };
+            arena.load(&mut scratch, node);
+            scratch.run_action(action, Condition::Normal);
+            match scratch.success {
+                Success::Failure => continue,
+                Success::Success => {
+                    let finished = arena.route(&craft, node, &scratch);
+                    #[cfg(not(feature="fast"))]
+                    if finished.quality >= finished.recipe.quality {
+                        keep_hq_route(&mut top_routes, &finished);
+                    }
+                    if top_route.as_ref().is_none_or(|top| is_better(&finished, top)) {
+                        control.found_route(&finished);
+                        #[cfg(feature="fast")]
+                        top_routes.push(finished.clone());
+                        top_route = Some(finished);
+                    }
+                    continue;
+                },
+                Success::Pending => {},
+            }
+            if is_over_steps(&scratch) {
+                continue;
+            }
+
+            let max_quality = bound.max_quality(&scratch.state(), scratch.recipe.progress - scratch.progression);
+            let can_beat = match (scratch.args.objective, &top_route) {
+                (Some(objective), Some(top)) => objective.can_beat(max_quality, &scratch, &top.state()),
+                (None, Some(top)) => max_quality > top.quality,
+                (_, None) => true,
+            };
+            if !can_beat {
+                continue;
+            }
+            // The steps are only compared for an objective, without one the search is after the quality
+            let steps = if scratch.args.objective.is_some() { scratch.step_count } else { 0 };
+            let values = (scratch.quality, scratch.cp, scratch.durability, steps);
+            if exact::is_dominated(reached.entry((scratch.progression, scratch.buffs)).or_default(), values) {
+                continue;
+            }
+            let score = (preferred.contains(&Some(action)), max_quality);
+            children.push((score, scratch.state(), action.id));
+        }
+        // The children are added to the arena in the order they are stacked
+        children.sort_by_key(|(score, _, _)| *score);
+        for (_, state, action) in children {
+            queue.push(arena.push(state, node, action));
+        }
+    }
+    if top_route.is_none() {
+        // The arena only holds the crafts still to search and the ones leading to them
+        let mut open = arena.ids().collect::<Vec<_>>();
+        open.sort_by_key(|&node| { let state = arena.state(node); (state.quality, state.progression) });
+        top_route = open.into_iter().rev().find_map(|node| {
+            arena.load(&mut scratch, node);
+            finisher.finish_craft(&scratch).map(|finished| arena.route(&craft, node, &finished))
+        });
+        if let Some(finished) = &top_route {
+            control.found_route(finished);
+        }
+    }
+    // The best route is already kept when it reaches the recipe quality
+    top_routes.extend(top_route.filter(|top| !top_routes.iter().any(|route| route.state() == top.state())));
+    (top_routes, nb_states)
+}
+
+/// Search the actions of both phases step by step, keeping only the crafts of each step with the best
+/// heuristic score, up to the beam width. Routes are done like the unified ones, the best ones found are returned
+/// Quick but approximate, the routes left out of the beam are never searched
+/// When stopped, the best route found so far is kept
+pub fn generate_routes_beam<'a>(craft: Craft<'a>, bound: &QualityBound, control: &SearchControl) -> (Vec<Craft<'a>>, usize) {
+    let actions = craft.action_list();
+    let width = craft.args.beam_width.max(1);
+    let heuristic = craft.args.beam_heuristic;
+    let mut arena = NodeArena::new();
+    let mut scratch = craft.clone();
+    let mut finisher = Finisher::new(&craft);
+    // Whether Byregot's Blessing was used is kept along the nodes
+    let mut beam = vec![(arena.root(craft.state()), false)];
+    let mut top_route: Option<Craft<'a>> = None;
+    let mut top_routes: Vec<Craft<'a>> = vec![];
+    let mut nb_states: usize = 0;
+
+    'search: while !beam.is_empty() {
+        let mut children = Vec::new();
+        // The same craft reached from two crafts of the beam is kept once
+        let mut seen = HashSet::new();
+        for &(node, node_byregot_used) in &beam {
+            nb_states += 1;
+            if nb_states.is_multiple_of(CHECK_INTERVAL) && control.check(CHECK_INTERVAL) {
+                break 'search;
+            }
+            arena.load(&mut scratch, node);
+            for action in next_action_phases(&scratch, node_byregot_used) {
+                arena.load(&mut scratch, node);
+                let action = match action {
+                    Some(a) if a.can_use(&scratch) && a.get_success_rate(&scratch) >= 100 => a,
+                    _ => continue,
+                };
+                scratch.run_action(action, Condition::Normal);
+                if scratch.success != Success::Pending || is_over_steps(&scratch) {
+                    continue;
+                }
+                let byregot_used = node_byregot_used || action == &actions.byregot_blessing;
+                let remaining_prog = remaining_progression(&scratch);
+
+                // The last synthesis is run after the touches, the route is only kept when there is one
+                if action.progress > 0 && remaining_prog <= 2.0 && top_route.is_none() {
+                    if let Some(finished) = finisher.finish_craft(&scratch) { // Default route, no hq on that one
+                        let finished = arena.route(&craft, node, &finished);
+                        control.found_route(&finished);
+                        top_route = Some(finished);
+                    }
+                }
+
+                if byregot_used && remaining_prog <= 2.0 {
+                    let Some(finished) = finisher.finish_craft(&scratch) else { continue };
+                    let finished = arena.route(&craft, node, &finished);
+                    #[cfg(not(feature="fast"))]
+                    if finished.quality >= finished.recipe.quality {
+                        keep_hq_route(&mut top_routes, &finished);
+                    }
+                    if top_route.as_ref().is_none_or(|top| is_better(&finished, top)) {
+                        control.found_route(&finished);
+                        #[cfg(feature="fast")]
+                        top_routes.push(finished.clone());
+                        top_route = Some(finished);
+                    }
+                    continue;
+                }
+
+                let max_quality = bound.max_quality(&scratch.state(), scratch.recipe.progress - scratch.progression);
+                let can_beat = match (scratch.args.objective, &top_route) {
+                    (Some(objective), Some(top)) => objective.can_beat(max_quality, &scratch, &top.state()),
+                    (None, Some(top)) => max_quality > top.quality,
+                    (_, None) => true,
+                };
+                if !can_beat || !seen.insert((scratch.state(), byregot_used)) {
+                    continue;
+                }
+                let score = heuristic.score(&scratch.state(), max_quality, scratch.recipe.quality);
+                children.push((score, scratch.state(), node, action.id, byregot_used));
+            }
+        }
+        // Stable, the crafts with the same score are kept in the order they were reached
+        children.sort_by_key(|(score, ..)| std::cmp::Reverse(*score));
+        children.truncate(width);
+        beam = children.into_iter()
+            .map(|(_, state, parent, action, byregot_used)| (arena.push(state, parent, action), byregot_used))
+            .collect();
+    }
+    // The best route is already kept when it reaches the recipe quality
+    top_routes.extend(top_route.filter(|top| !top_routes.iter().any(|route| route.state() == top.state())));
+    (top_routes, nb_states)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::craft::fixtures::{craft, params};
+    use crate::exact::ExactSolver;
+    use super::*;
+
+    #[test]
+    fn unified_reaches_the_exact_quality() {
+        for (durability, progress, max_cp) in [(20, 600, 40), (40, 300, 60)] {
+            // The quality is out of reach, so both searches are after the most quality
+            let craft = craft(durability, progress, 100000, max_cp, params());
+            let control = SearchControl::default();
+            let exact = ExactSolver::new(&craft, &control).solve(craft.clone()).unwrap();
+            let (routes, _) = generate_routes_unified(craft.clone(), &QualityBound::new(&craft), &control);
+            let best = routes.iter().map(|route| route.quality).max().unwrap();
+            assert_eq!(best, exact.quality, "{:?}", (durability, progress, max_cp));
+        }
+    }
+
+    #[test]
+    fn keeps_only_the_hq_routes_not_beaten() {
+        let craft = craft(40, 600, 500, 100, params());
+        let control = SearchControl::default();
+        let (routes, _) = generate_routes_unified(craft.clone(), &QualityBound::new(&craft), &control);
+        assert!(routes.len() > 1);
+        // The route with the most quality is kept even when beaten
+        let best = routes.iter().map(|route| route.quality).max().unwrap();
+        for route in routes.iter().filter(|route| route.quality < best) {
+            assert!(route.quality >= craft.recipe.quality);
+            assert!(!routes.iter().any(|other| other.state() != route.state() && other.step_count <= route.step_count
+                && other.durability >= route.durability && other.cp >= route.cp));
+        }
+    }
+
+    #[test]
+    fn finisher_replays_its_actions() {
+        let mut craft = craft(40, 2000, 5000, 100, params());
+        let actions = craft.action_list();
+        for action in [&actions.veneration, &actions.basic_synthesis, &actions.basic_synthesis] {
+            craft.run_action(action, Condition::Normal);
+        }
+        let mut finisher = Finisher::new(&craft);
+        let route = finisher.finish_craft(&craft).unwrap();
+        assert_eq!(route.success, Success::Success);
+        assert!(route.step_count - craft.step_count <= FINISHER_STEPS);
+        assert_eq!(route.actions[..craft.actions.len()], craft.actions[..]);
+
+        // Running the finisher again gives the same state
+        let mut replay = craft.clone();
+        for &action in &route.actions[craft.actions.len()..] {
+            replay.run_action(action, Condition::Normal);
+        }
+        assert_eq!(replay.state(), route.state());
+        assert_eq!(finisher.finish(craft.state()).map(|(state, _)| *state), Some(route.state()));
+
+        // Nothing finishes a craft without durability left for a synthesis
+        craft.durability = 0;
+        assert!(finisher.finish(craft.state()).is_none());
+    }
+}
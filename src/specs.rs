@@ -1,6 +1,50 @@
 use std::fmt::{Debug};
-use strum_macros::EnumIter;
+use strum_macros::{EnumIter, EnumString};
+use clap::ValueEnum;
+use crate::action::{ActionId, ActionList, ACTIONS, ACTIONS_ENDWALKER};
+use crate::recipe_level;
 
+/// The game patch whose actions are used to simulate the craft. The base formulas and Inner Quiet work the same in
+/// 6.x and 7.x, the versions differ by their action table, the actions they have and the Observe combo of Advanced Touch
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum GameVersion {
+    /// Patch 6.x
+    #[value(name = "6", alias = "6.x", alias = "endwalker")]
+    Endwalker,
+    /// Patch 7.x, the live game
+    #[value(name = "7", alias = "7.x", alias = "dawntrail")]
+    Dawntrail,
+}
+
+impl GameVersion {
+    /// The action table of this version
+    pub fn actions(&self) -> &'static ActionList {
+        match self {
+            GameVersion::Endwalker => &ACTIONS_ENDWALKER,
+            GameVersion::Dawntrail => &ACTIONS,
+        }
+    }
+
+    /// Whether the action exists in this version
+    pub fn has_action(&self, id: ActionId) -> bool {
+        match self {
+            GameVersion::Endwalker => !matches!(id, ActionId::ImmaculateMend | ActionId::TrainedPerfection
+                | ActionId::RefinedTouch | ActionId::DaringTouch | ActionId::QuickInnovation),
+            GameVersion::Dawntrail => true,
+        }
+    }
+}
+
+impl std::str::FromStr for GameVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+/// The level cap of the crafters, used when a level is not given
+pub const MAX_LEVEL: u32 = 100;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Recipe {
@@ -11,6 +55,53 @@ pub struct Recipe {
     pub quality_divider: u32,
     pub progress_modifier: u32,
     pub quality_modifier: u32,
+    pub expert: bool,
+    pub rlvl: u32,
+    pub class_job_level: u32,
+}
+
+impl Recipe {
+    /// Make a recipe from its level, the dividers and modifiers come from the built-in recipe level table
+    pub fn from_rlvl(rlvl: u32, progress: u32, quality: u32, durability: u32) -> Option<Recipe> {
+        let level = recipe_level::find(rlvl)?;
+        Some(Recipe {
+            durability,
+            progress,
+            quality,
+            progress_divider: level.progress_divider,
+            quality_divider: level.quality_divider,
+            progress_modifier: level.progress_modifier,
+            quality_modifier: level.quality_modifier,
+            expert: false,
+            rlvl,
+            class_job_level: level.class_job_level,
+        })
+    }
+
+    /// The conditions the game can roll for this recipe
+    pub fn conditions(&self) -> &'static [Condition] {
+        if self.expert {
+            &[Condition::Normal, Condition::Good, Condition::Centered, Condition::Sturdy,
+              Condition::Pliant, Condition::Malleable, Condition::Primed]
+        } else {
+            &[Condition::Normal, Condition::Good, Condition::Excellent, Condition::Poor]
+        }
+    }
+
+    /// The conditions the game can roll for the next step after this one, with their chances
+    /// The rates are the ones measured by the players: on a normal recipe Good and Excellent only follow Normal,
+    /// Excellent is always followed by Poor; on an expert recipe every step is rolled the same way
+    pub fn next_conditions(&self, condition: Condition) -> &'static [(Condition, f64)] {
+        if self.expert {
+            return &[(Condition::Normal, 0.22), (Condition::Good, 0.12), (Condition::Centered, 0.15), (Condition::Sturdy, 0.15),
+                (Condition::Pliant, 0.12), (Condition::Malleable, 0.12), (Condition::Primed, 0.12)];
+        }
+        match condition {
+            Condition::Excellent => &[(Condition::Poor, 1.0)],
+            Condition::Normal => &[(Condition::Normal, 0.71), (Condition::Good, 0.25), (Condition::Excellent, 0.04)],
+            _ => &[(Condition::Normal, 1.0)],
+        }
+    }
 }
 
 
@@ -19,9 +110,12 @@ pub struct Stats {
     pub craftsmanship: u32,
     pub control: u32,
     pub max_cp: u32,
+    pub specialist: bool,
+    pub level: u32,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, EnumIter)]
+#[derive(Debug, Clone, Copy, PartialEq, EnumIter, EnumString)]
+#[strum(serialize_all = "snake_case")]
 pub enum Buff {
     InnerQuiet,
     WasteNot,
@@ -30,13 +124,25 @@ pub enum Buff {
     Veneration,
     Manipulation,
     MuscleMemory,
+    FinalAppraisal,
+    HeartAndSoul,
+    TrainedPerfection,
+    Expedience,
     // v Hidden v
     BasicTouch,
     StandardTouch,
     Observe,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Buff {
+    /// Whether the buff lasts a number of steps (and can be extended by Primed)
+    pub fn is_timed(&self) -> bool {
+        matches!(self, Buff::WasteNot | Buff::GreatStrides | Buff::Innovation
+            | Buff::Veneration | Buff::Manipulation | Buff::MuscleMemory | Buff::FinalAppraisal)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct BuffState {
     pub inner_quiet: u8,
     pub waste_not: u8,
@@ -45,9 +151,18 @@ pub struct BuffState {
     pub veneration: u8,
     pub manipulation: u8,
     pub muscle_memory: u8,
+    pub final_appraisal: u8,
+    pub heart_and_soul: u8,
+    pub trained_perfection: u8,
+    pub expedience: u8,
     pub basic_touch: u8,
     pub standard_touch: u8,
     pub observe: u8,
+    // Number of uses of the limited actions
+    pub careful_observation: u8,
+    pub heart_and_soul_used: u8,
+    pub trained_perfection_used: u8,
+    pub quick_innovation_used: u8,
 }
 
 impl BuffState {
@@ -60,9 +175,17 @@ impl BuffState {
             veneration: 0,
             manipulation: 0,
             muscle_memory: 0,
+            final_appraisal: 0,
+            heart_and_soul: 0,
+            trained_perfection: 0,
+            expedience: 0,
             basic_touch: 0,
             standard_touch: 0,
             observe: 0,
+            careful_observation: 0,
+            heart_and_soul_used: 0,
+            trained_perfection_used: 0,
+            quick_innovation_used: 0,
         }
     }
 
@@ -75,6 +198,10 @@ impl BuffState {
             Buff::Veneration => self.veneration = 0,
             Buff::Manipulation => self.manipulation = 0,
             Buff::MuscleMemory => self.muscle_memory = 0,
+            Buff::FinalAppraisal => self.final_appraisal = 0,
+            Buff::HeartAndSoul => self.heart_and_soul = 0,
+            Buff::TrainedPerfection => self.trained_perfection = 0,
+            Buff::Expedience => self.expedience = 0,
             Buff::BasicTouch => self.basic_touch = 0,
             Buff::StandardTouch => self.standard_touch = 0,
             Buff::Observe => self.observe = 0,
@@ -96,9 +223,39 @@ impl BuffState {
             Buff::Veneration => self.veneration = value,
             Buff::Manipulation => self.manipulation = value,
             Buff::MuscleMemory => self.muscle_memory = value,
+            Buff::FinalAppraisal => self.final_appraisal = value,
+            Buff::HeartAndSoul => {
+                self.heart_and_soul = value;
+                self.heart_and_soul_used = 1;
+            }
+            Buff::TrainedPerfection => {
+                self.trained_perfection = value;
+                self.trained_perfection_used = 1;
+            }
+            Buff::Expedience => self.expedience = value,
             Buff::BasicTouch => self.basic_touch = value,
             Buff::StandardTouch => self.standard_touch = value,
-            Buff::Observe => self.standard_touch = value,
+            Buff::Observe => self.observe = value,
+        }
+    }
+
+    /// The steps left of a buff, or its stacks for Inner Quiet
+    pub fn get(&self, buff: Buff) -> u8 {
+        match buff {
+            Buff::InnerQuiet => self.inner_quiet,
+            Buff::WasteNot => self.waste_not,
+            Buff::GreatStrides => self.great_strides,
+            Buff::Innovation => self.innovation,
+            Buff::Veneration => self.veneration,
+            Buff::Manipulation => self.manipulation,
+            Buff::MuscleMemory => self.muscle_memory,
+            Buff::FinalAppraisal => self.final_appraisal,
+            Buff::HeartAndSoul => self.heart_and_soul,
+            Buff::TrainedPerfection => self.trained_perfection,
+            Buff::Expedience => self.expedience,
+            Buff::BasicTouch => self.basic_touch,
+            Buff::StandardTouch => self.standard_touch,
+            Buff::Observe => self.observe,
         }
     }
 
@@ -111,12 +268,103 @@ impl BuffState {
         if self.muscle_memory > 0 { self.muscle_memory -= 1; }
         if self.basic_touch > 0 { self.basic_touch -= 1; }
         if self.standard_touch > 0 { self.standard_touch -= 1; }
+        if self.final_appraisal > 0 { self.final_appraisal -= 1; }
+        if self.observe > 0 { self.observe -= 1; }
+        if self.expedience > 0 { self.expedience -= 1; }
+    }
+}
+
+/// The condition of the craft, rolled by the game before each step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter)]
+pub enum Condition {
+    Normal,
+    Good,
+    Excellent,
+    Poor,
+    // v Expert recipes v
+    Centered,
+    Sturdy,
+    Pliant,
+    Malleable,
+    Primed,
+}
+
+impl Condition {
+    /// Multiplier applied to the quality of an action done under this condition
+    pub fn quality_multiplier(&self) -> f64 {
+        match self {
+            Condition::Normal => 1.0,
+            Condition::Good => 1.5,
+            Condition::Excellent => 4.0,
+            Condition::Poor => 0.5,
+            _ => 1.0,
+        }
+    }
+
+    /// Multiplier applied to the progress of an action done under this condition
+    pub fn progress_multiplier(&self) -> f64 {
+        match self {
+            Condition::Malleable => 1.5,
+            _ => 1.0,
+        }
+    }
+
+    /// Bonus added to the success rate of an action, in percent
+    pub fn success_bonus(&self) -> u32 {
+        match self {
+            Condition::Centered => 25,
+            _ => 0,
+        }
+    }
+
+    /// Additional steps given to a buff applied under this condition
+    pub fn buff_bonus(&self) -> u8 {
+        match self {
+            Condition::Primed => 2,
+            _ => 0,
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Success {
     Pending,
     Success,
     Failure,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::craft::fixtures::{craft, params};
+    use crate::io::Parameters;
+    use super::*;
+
+    #[test]
+    fn versions_diverge() {
+        let run = |game_version: GameVersion| {
+            let mut craft = craft(70, 5000, 10000, 600, Parameters { game_version, ..params() });
+            for id in [ActionId::Reflect, ActionId::CarefulSynthesis] {
+                craft.run_action(craft.action_list().get(id), Condition::Normal);
+            }
+            (craft.progression, craft.quality)
+        };
+        let (endwalker, dawntrail) = (run(GameVersion::Endwalker), run(GameVersion::Dawntrail));
+        // Careful Synthesis has 150 potency in 6.x and 180 in 7.x
+        assert!(endwalker.0 < dawntrail.0, "{:?} {:?}", endwalker, dawntrail);
+        // Reflect has 100 potency in 6.x and 300 in 7.x
+        assert!(endwalker.1 < dawntrail.1, "{:?} {:?}", endwalker, dawntrail);
+    }
+
+    #[test]
+    fn next_conditions_are_chances() {
+        for expert in [false, true] {
+            let recipe = Recipe { expert, ..Recipe::from_rlvl(560, 1000, 1000, 40).unwrap() };
+            for &condition in recipe.conditions() {
+                let next = recipe.next_conditions(condition);
+                let total: f64 = next.iter().map(|&(_, chance)| chance).sum();
+                assert!((total - 1.0).abs() < 1e-9, "{:?} after {:?}: {}", expert, condition, total);
+                assert!(next.iter().all(|(next, chance)| recipe.conditions().contains(next) && *chance > 0.0));
+            }
+        }
+    }
+}